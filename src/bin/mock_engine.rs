@@ -0,0 +1,95 @@
+//! A deliberately simple engine for the tests of [`uttprotocol::process`] and
+//! [`uttprotocol::tournament`]. It plays the first legal move, and the first argument makes it
+//! misbehave:
+//! - `first` (default) answers at once
+//! - `stop` searches until told to stop
+//! - `crash` exits as soon as it is asked to search
+//! - `hang` ignores `stop` and never answers
+//! - `illegal` always plays `a1`
+//! - `mute` never finishes the handshake
+//! - `chatty` also prints lines that aren't commands and `info` fields that UTP doesn't have
+
+use std::{
+	env,
+	io::{self, BufRead},
+	process,
+	sync::atomic::{AtomicBool, Ordering},
+	thread,
+	time::Duration,
+};
+
+use uttprotocol::{
+	driver::{Engine, EngineDriver},
+	protocol::{GoParams, Info},
+	state::{Move, State},
+};
+
+struct Mock {
+	mode: String,
+	state: State,
+}
+
+impl Engine for Mock {
+	fn name(&self) -> &str {
+		"Mock"
+	}
+
+	fn author(&self) -> &str {
+		"uttprotocol"
+	}
+
+	fn set_option(&mut self, _name: &str, _value: Option<&str>) {}
+
+	fn set_position(&mut self, state: State) {
+		self.state = state;
+	}
+
+	fn go(
+		&mut self,
+		_params: &GoParams,
+		stop: &AtomicBool,
+		info: &mut dyn FnMut(Info),
+	) -> Option<Move> {
+		match self.mode.as_str() {
+			"crash" => process::exit(3),
+			"hang" => loop {
+				thread::sleep(Duration::from_secs(60));
+			},
+			"illegal" => return Some("a1".parse().unwrap()),
+			"chatty" => {
+				println!("thinking about {}", self.state);
+				println!("info depth 2 seldepth 3 nps 1000 currmove e5 hashfull 0");
+			}
+			"stop" => {
+				while !stop.load(Ordering::Relaxed) {
+					thread::sleep(Duration::from_millis(1));
+				}
+			}
+			_ => {}
+		}
+
+		info(Info {
+			depth: Some(1),
+			..Info::default()
+		});
+		self.state.legal_moves().next()
+	}
+}
+
+fn main() -> io::Result<()> {
+	let mode = env::args().nth(1).unwrap_or_else(|| "first".into());
+
+	if mode == "mute" {
+		// Swallow everything without answering
+		return io::stdin()
+			.lock()
+			.lines()
+			.try_for_each(|line| line.map(drop));
+	}
+
+	EngineDriver::new(Mock {
+		mode,
+		state: State::default(),
+	})
+	.run(io::stdin().lock(), io::stdout())
+}
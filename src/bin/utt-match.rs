@@ -0,0 +1,206 @@
+//! Plays a match or tournament between engines and prints the standings
+//!
+//! ```text
+//! utt-match [options] --engine <command> --engine <command> [--engine <command>...]
+//! ```
+//!
+//! Engine commands are split on whitespace into a program and its arguments. Options:
+//! - `--games <n>` games per pairing, 2 by default
+//! - `--movetime <ms>` time per move, 100 by default
+//! - `--grace <ms>` time allowed for starting up and answering `stop`, 1000 by default
+//! - `--gauntlet` only pair the first engine with the others, instead of a round robin
+//! - `--openings <file>` start games from the UTT strings in `file`, one per line, `#` comments
+//! - `--log <file>` write the record of every game to `file`, see [`uttprotocol::game`]
+//! - `--no-adjudicate` play games out even when both engines agree on the winner
+//! - `--sprt` stop a match between two engines once an SPRT of the first against the second is
+//!   decided, testing `elo <= 0` against `elo >= 5` with 5% error rates by default
+//! - `--elo0 <elo>`, `--elo1 <elo>`, `--alpha <p>`, `--beta <p>` set the SPRT's parameters, and
+//!   imply `--sprt`
+//!
+//! A match between two engines also reports the first engine's Elo difference with its 95%
+//! confidence interval, likelihood of superiority and the SPRT's log-likelihood ratio.
+
+use std::{
+	env,
+	fs::{self, File},
+	io::{self, BufWriter, Write},
+	process::ExitCode,
+	time::Duration,
+};
+
+use uttprotocol::{
+	state::State,
+	stats::{Sample, Verdict},
+	tournament::{Config, Format, Player, Tournament},
+};
+
+struct Args {
+	config: Config,
+	/// Program and arguments of every engine
+	engines: Vec<(String, Vec<String>)>,
+	log: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+	let mut args = Args {
+		config: Config::default(),
+		engines: vec![],
+		log: None,
+	};
+
+	let mut iter = env::args().skip(1);
+	while let Some(arg) = iter.next() {
+		let mut value = || iter.next().ok_or_else(|| format!("{arg} needs a value"));
+		let number = |value: String| {
+			value
+				.parse::<f64>()
+				.map_err(|_| format!("invalid number {value:?}"))
+		};
+		let millis = |value: String| {
+			value
+				.parse()
+				.map(Duration::from_millis)
+				.map_err(|_| format!("invalid time {value:?}"))
+		};
+
+		match arg.as_str() {
+			"--engine" => {
+				let command = value()?;
+				let mut words = command.split_whitespace().map(str::to_string);
+				let program = words.next().ok_or("empty engine command")?;
+				args.engines.push((program, words.collect()));
+			}
+			"--games" => {
+				let games = value()?;
+				args.config.games = games
+					.parse()
+					.map_err(|_| format!("invalid game count {games:?}"))?;
+			}
+			"--movetime" => args.config.movetime = millis(value()?)?,
+			"--grace" => args.config.grace = millis(value()?)?,
+			"--gauntlet" => args.config.format = Format::Gauntlet,
+			"--openings" => args.config.openings = read_openings(&value()?)?,
+			"--log" => args.log = Some(value()?),
+			"--no-adjudicate" => args.config.adjudicate = false,
+			"--sprt" => {
+				args.config.sprt.get_or_insert_default();
+			}
+			"--elo0" => args.config.sprt.get_or_insert_default().elo0 = number(value()?)?,
+			"--elo1" => args.config.sprt.get_or_insert_default().elo1 = number(value()?)?,
+			"--alpha" => args.config.sprt.get_or_insert_default().alpha = number(value()?)?,
+			"--beta" => args.config.sprt.get_or_insert_default().beta = number(value()?)?,
+			_ => return Err(format!("unknown argument {arg:?}")),
+		}
+	}
+
+	if args.engines.len() < 2 {
+		return Err("at least two engines are needed".into());
+	}
+
+	Ok(args)
+}
+
+fn read_openings(path: &str) -> Result<Vec<State>, String> {
+	let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+
+	text.lines()
+		.enumerate()
+		.map(|(n, line)| (n, line.trim()))
+		.filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+		.map(|(n, line)| {
+			line.parse()
+				.map_err(|err| format!("{path}:{}: {err}", n + 1))
+		})
+		.collect()
+}
+
+/// Prints statistics of the first engine against the second
+fn summarize(tournament: &Tournament) {
+	let wdl = tournament.wdl(0, 1);
+	let penta = tournament.pentanomial(0, 1);
+	let Some(elo) = wdl.elo() else {
+		return;
+	};
+
+	println!();
+	println!(
+		"{} vs {}: +{} ={} -{}",
+		tournament.players[0].name, tournament.players[1].name, wdl.wins, wdl.draws, wdl.losses
+	);
+	println!("Elo: {elo}, LOS: {:.1}%", 100.0 * wdl.los().unwrap());
+	if let Some(elo) = penta.elo() {
+		println!("Pairs: {:?}, Elo: {elo}", penta.counts);
+	}
+
+	if let Some(sprt) = tournament.config.sprt {
+		let (lower, upper) = sprt.bounds();
+		let verdict = match tournament.sprt() {
+			Verdict::Continue => "undecided",
+			Verdict::H0 => "H0 accepted",
+			Verdict::H1 => "H1 accepted",
+		};
+		println!(
+			"SPRT [{}, {}]: LLR {:.2} ({lower:.2}, {upper:.2}), {verdict}",
+			sprt.elo0,
+			sprt.elo1,
+			sprt.llr(&penta)
+		);
+	}
+}
+
+fn main() -> ExitCode {
+	let args = match parse_args() {
+		Ok(args) => args,
+		Err(err) => {
+			eprintln!("utt-match: {err}");
+			return ExitCode::from(2);
+		}
+	};
+
+	let mut log = match args.log.as_deref().map(File::create).transpose() {
+		Ok(log) => log.map(BufWriter::new),
+		Err(err) => {
+			eprintln!("utt-match: {}: {err}", args.log.unwrap());
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let players = args
+		.engines
+		.into_iter()
+		.map(|(program, args)| Player::new(program, args))
+		.collect();
+	let mut tournament = Tournament::new(players, args.config);
+
+	let mut res = Ok(());
+	tournament.run(|tournament, game| {
+		let number = tournament.games.len() + 1;
+		println!(
+			"game {number}: {} vs {}, {} by {}",
+			tournament.players[game.x].name,
+			tournament.players[game.o].name,
+			game.result,
+			game.termination,
+		);
+
+		if let Some(log) = &mut log
+			&& res.is_ok()
+		{
+			res = tournament
+				.write_game(&mut *log, number, game)
+				.and_then(|_| log.flush());
+		}
+	});
+
+	println!();
+	let res = res.and_then(|_| tournament.write_table(io::stdout().lock()));
+	if tournament.players.len() == 2 {
+		summarize(&tournament);
+	}
+	if let Err(err) = res {
+		eprintln!("utt-match: {err}");
+		return ExitCode::FAILURE;
+	}
+
+	ExitCode::SUCCESS
+}
@@ -0,0 +1,320 @@
+//! Bitboard representation of a [`State`] for engines
+//! Each side's marks are stored as an 81 bit mask in a `u128`, where bit `i` is
+//! [`State::squares`]`[i]` (see [`Move::index`]), so sub-board `b` is bits `9 * b..9 * b + 9`.
+//! Won and closed boards are tracked as 9 bit masks and kept up to date as moves are played, so
+//! move generation never has to look at the squares of a board that can't be played in.
+
+use crate::state::{GameResult, Move, MoveErr, Square, State};
+
+/// The 8 lines of 3 cells that win a board (or the meta-board), as 9 bit masks
+const LINES: [u16; 8] = [
+	0b000_000_111,
+	0b000_111_000,
+	0b111_000_000,
+	0b001_001_001,
+	0b010_010_010,
+	0b100_100_100,
+	0b100_010_001,
+	0b001_010_100,
+];
+
+const FULL: u16 = 0x1ff;
+
+fn has_line(cells: u16) -> bool {
+	LINES.iter().any(|&line| line & !cells == 0)
+}
+
+/// The 9 cells of `board` in `mask`
+fn board_bits(mask: u128, board: u8) -> u16 {
+	(mask >> (board as u32 * 9)) as u16 & FULL
+}
+
+/// A [`State`] stored as bitboards, converting to and from a [`State`] is lossless
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitState {
+	x: u128,
+	o: u128,
+	x_won: u16,
+	o_won: u16,
+	/// Boards that are won by either side or full
+	closed: u16,
+	active: u8,
+	last_move: Option<Move>,
+	side: Square,
+}
+
+/// What [`BitState::undo`] needs to take back a move made with [`BitState::play`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+	mv: Move,
+	x_won: u16,
+	o_won: u16,
+	closed: u16,
+	active: u8,
+	last_move: Option<Move>,
+}
+
+impl BitState {
+	pub fn x(&self) -> u128 {
+		self.x
+	}
+
+	pub fn o(&self) -> u128 {
+		self.o
+	}
+
+	/// Boards that are won by either side or full, as a 9 bit mask
+	pub fn closed(&self) -> u16 {
+		self.closed
+	}
+
+	pub fn active(&self) -> u8 {
+		self.active
+	}
+
+	pub fn last_move(&self) -> Option<Move> {
+		self.last_move
+	}
+
+	pub fn side_to_move(&self) -> Square {
+		self.side
+	}
+
+	pub fn square(&self, mv: Move) -> Square {
+		let bit = 1 << mv.index();
+
+		if self.x & bit != 0 {
+			Square::X
+		} else if self.o & bit != 0 {
+			Square::O
+		} else {
+			Square::Empty
+		}
+	}
+
+	/// Recomputes the won and closed bits of `board`
+	fn update_board(&mut self, board: u8) {
+		let bit = 1 << board;
+		let (x, o) = (board_bits(self.x, board), board_bits(self.o, board));
+
+		// A board can only be won once, later marks on it don't change who won it
+		if self.closed & bit != 0 {
+			return;
+		}
+
+		if has_line(x) {
+			self.x_won |= bit;
+		} else if has_line(o) {
+			self.o_won |= bit;
+		}
+
+		if self.x_won & bit != 0 || self.o_won & bit != 0 || x | o == FULL {
+			self.closed |= bit;
+		}
+	}
+
+	pub fn result(&self) -> GameResult {
+		if has_line(self.x_won) {
+			GameResult::XWins
+		} else if has_line(self.o_won) {
+			GameResult::OWins
+		} else if self.closed == FULL {
+			GameResult::Draw
+		} else {
+			GameResult::Ongoing
+		}
+	}
+
+	/// Mask of every square the side to move may play on, empty once the game is decided
+	pub fn legal_mask(&self) -> u128 {
+		if self.result() != GameResult::Ongoing {
+			return 0;
+		}
+
+		let boards = if self.active > 8 || self.closed & (1 << self.active) != 0 {
+			!self.closed & FULL
+		} else {
+			1 << self.active
+		};
+
+		let mut mask = 0;
+		for board in 0..9 {
+			if boards & (1 << board) != 0 {
+				mask |= (FULL as u128) << (board * 9);
+			}
+		}
+
+		mask & !(self.x | self.o)
+	}
+
+	/// All legal moves in this position, in [`Move::index`] order
+	pub fn legal_moves(&self) -> impl Iterator<Item = Move> + use<> {
+		let mut mask = self.legal_mask();
+
+		std::iter::from_fn(move || {
+			if mask == 0 {
+				return None;
+			}
+
+			let index = mask.trailing_zeros() as usize;
+			mask &= mask - 1;
+			Move::from_index(index)
+		})
+	}
+
+	pub fn is_legal(&self, mv: Move) -> bool {
+		self.legal_mask() & (1 << mv.index()) != 0
+	}
+
+	/// Plays a move for the side to move, see [`State::play`]
+	pub fn play(&mut self, mv: Move) -> Result<Undo, MoveErr> {
+		if !self.is_legal(mv) {
+			return Err(MoveErr::Illegal);
+		}
+
+		let undo = Undo {
+			mv,
+			x_won: self.x_won,
+			o_won: self.o_won,
+			closed: self.closed,
+			active: self.active,
+			last_move: self.last_move,
+		};
+
+		let bit = 1 << mv.index();
+		self.side = match self.side {
+			Square::O => {
+				self.o |= bit;
+				Square::X
+			}
+			_ => {
+				self.x |= bit;
+				Square::O
+			}
+		};
+
+		self.update_board(mv.board());
+		self.last_move = Some(mv);
+		self.active = if self.closed & (1 << mv.cell()) != 0 {
+			9
+		} else {
+			mv.cell()
+		};
+
+		Ok(undo)
+	}
+
+	/// Takes back the move that produced `undo`, which must be the last move played
+	pub fn undo(&mut self, undo: Undo) {
+		let bit = !(1 << undo.mv.index());
+		self.x &= bit;
+		self.o &= bit;
+		self.x_won = undo.x_won;
+		self.o_won = undo.o_won;
+		self.closed = undo.closed;
+		self.active = undo.active;
+		self.last_move = undo.last_move;
+		self.side = match self.side {
+			Square::X => Square::O,
+			_ => Square::X,
+		};
+	}
+}
+
+impl From<&State> for BitState {
+	fn from(state: &State) -> Self {
+		let mut bits = BitState {
+			x: 0,
+			o: 0,
+			x_won: 0,
+			o_won: 0,
+			closed: 0,
+			active: state.active,
+			last_move: state.last_move,
+			side: state.side_to_move(),
+		};
+
+		for (i, sq) in state.squares.iter().enumerate() {
+			match sq {
+				Square::X => bits.x |= 1 << i,
+				Square::O => bits.o |= 1 << i,
+				Square::Empty => {}
+			}
+		}
+
+		for board in 0..9 {
+			bits.update_board(board);
+		}
+
+		bits
+	}
+}
+
+impl From<State> for BitState {
+	fn from(state: State) -> Self {
+		BitState::from(&state)
+	}
+}
+
+impl From<&BitState> for State {
+	fn from(bits: &BitState) -> Self {
+		State {
+			active: bits.active,
+			squares: std::array::from_fn(|i| bits.square(Move::from_index(i).unwrap())),
+			last_move: bits.last_move,
+		}
+	}
+}
+
+impl From<BitState> for State {
+	fn from(bits: BitState) -> Self {
+		State::from(&bits)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{state::parse, testing::Lcg};
+
+	#[test]
+	fn agrees_with_state() {
+		let mut rng = Lcg::new(0x853c_49e6_748f_ea9b);
+
+		for _ in 0..200 {
+			let mut state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+			let mut bits = BitState::from(&state);
+			let mut history = vec![];
+
+			loop {
+				assert_eq!(State::from(&bits), state);
+				assert_eq!(bits.result(), state.result());
+				assert_eq!(bits.side_to_move(), state.side_to_move());
+				assert!(bits.legal_moves().eq(state.legal_moves()));
+				assert_eq!(BitState::from(&state), bits);
+
+				let Some(mv) = rng.legal_move(&state) else {
+					break;
+				};
+
+				state.play(mv).unwrap();
+				history.push((bits.clone(), bits.play(mv).unwrap()));
+			}
+
+			for (before, undo) in history.into_iter().rev() {
+				bits.undo(undo);
+				assert_eq!(bits, before);
+			}
+		}
+	}
+
+	#[test]
+	fn rejects_illegal_moves() {
+		let mut bits = BitState::from(parse("4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5").unwrap());
+
+		assert_eq!(bits.play("a1".parse().unwrap()), Err(MoveErr::Illegal));
+		assert_eq!(bits.play("e5".parse().unwrap()), Err(MoveErr::Illegal));
+		assert!(bits.play("e1".parse().unwrap()).is_ok());
+		assert_eq!(bits.square("e1".parse().unwrap()), Square::O);
+	}
+}
@@ -0,0 +1,396 @@
+//! Files of many [game records](crate::game), read one game at a time and appended to.
+//!
+//! Records are separated by blank lines, as [`GameWriter`] writes them. [`GameReader`] only holds
+//! the record it is parsing, ending it at the first blank line or header after its moves start
+//! that isn't inside a comment, so files of any size can be read. A header right after a blank
+//! line always starts a new record, so an unclosed comment only spoils its own record. A record
+//! that fails to parse is reported with the line and column of the error in the file, and reading
+//! carries on with the next one.
+
+use std::{
+	error::Error,
+	fmt,
+	fs::{File, OpenOptions},
+	io::{self, BufRead, BufReader, BufWriter, Write},
+	path::Path,
+};
+
+use crate::game::{self, Game, ParseError};
+
+#[derive(Debug)]
+pub enum ReadError {
+	/// Reading stops after an i/o error
+	Io(io::Error),
+	/// The record starting at line `record` is invalid, `line` and `column` locate the error in
+	/// the file, all counting from 1, while `err`'s span is relative to the record
+	Parse {
+		record: usize,
+		line: usize,
+		column: usize,
+		err: ParseError,
+	},
+}
+
+impl fmt::Display for ReadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ReadError::Io(err) => write!(f, "i/o error: {err}"),
+			ReadError::Parse {
+				line, column, err, ..
+			} => write!(f, "{line}:{column}: {err}"),
+		}
+	}
+}
+
+impl Error for ReadError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			ReadError::Io(err) => Some(err),
+			ReadError::Parse { err, .. } => Some(err),
+		}
+	}
+}
+
+impl From<io::Error> for ReadError {
+	fn from(err: io::Error) -> Self {
+		ReadError::Io(err)
+	}
+}
+
+/// Iterates over the games of a file, see the module documentation
+pub struct GameReader<R> {
+	input: R,
+	/// Lines read so far
+	line: usize,
+	/// A header line read while finishing the previous record, with its line number
+	pending: Option<(usize, String)>,
+	done: bool,
+}
+
+impl GameReader<BufReader<File>> {
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		Ok(Self::new(BufReader::new(File::open(path)?)))
+	}
+}
+
+impl<R: BufRead> GameReader<R> {
+	pub fn new(input: R) -> Self {
+		Self {
+			input,
+			line: 0,
+			pending: None,
+			done: false,
+		}
+	}
+
+	/// The text of the next record and the line it starts on, `None` at the end of the input
+	fn next_record(&mut self) -> io::Result<Option<(usize, String)>> {
+		let (mut start, mut text) = self.pending.take().unwrap_or_default();
+		let mut in_moves = false;
+		let mut in_comment = false;
+		let mut after_blank = false;
+
+		loop {
+			let mut line = String::new();
+			if self.input.read_line(&mut line)? == 0 {
+				self.done = true;
+				break;
+			}
+			self.line += 1;
+
+			// Blank lines and brackets inside a comment don't end the record, unless the bracket
+			// follows a blank line, which leaves an unclosed comment behind
+			let trimmed = line.trim();
+			let header = trimmed.starts_with('[') && (!in_comment || after_blank);
+			after_blank = trimmed.is_empty();
+			if header {
+				in_comment = false;
+			} else {
+				for c in trimmed.chars() {
+					match c {
+						'{' => in_comment = true,
+						'}' => in_comment = false,
+						_ => {}
+					}
+				}
+			}
+			if in_moves && !in_comment && (trimmed.is_empty() || header) {
+				if header {
+					self.pending = Some((self.line, line));
+				}
+				break;
+			}
+
+			if text.is_empty() {
+				if trimmed.is_empty() {
+					continue;
+				}
+				start = self.line;
+			}
+			in_moves |= !trimmed.is_empty() && !header;
+			text.push_str(&line);
+		}
+
+		Ok((!text.is_empty()).then_some((start, text)))
+	}
+}
+
+/// Turns a byte offset into `text` into a line and column, counting from 1
+fn locate(text: &str, offset: usize) -> (usize, usize) {
+	let before = &text[..offset.min(text.len())];
+	let line = before.matches('\n').count();
+	let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+		.chars()
+		.count();
+	(line + 1, column + 1)
+}
+
+impl<R: BufRead> Iterator for GameReader<R> {
+	type Item = Result<Game, ReadError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done && self.pending.is_none() {
+			return None;
+		}
+
+		let (record, text) = match self.next_record() {
+			Ok(record) => record?,
+			Err(err) => {
+				self.done = true;
+				self.pending = None;
+				return Some(Err(err.into()));
+			}
+		};
+
+		Some(game::parse(&text).map_err(|err| {
+			let (line, column) = locate(&text, err.span().start);
+			ReadError::Parse {
+				record,
+				line: record + line - 1,
+				column,
+				err,
+			}
+		}))
+	}
+}
+
+/// Writes games one after the other, each followed by a blank line
+pub struct GameWriter<W: Write> {
+	output: W,
+}
+
+impl GameWriter<BufWriter<File>> {
+	/// Opens `path` for appending, creating it if needed
+	pub fn append_to(path: impl AsRef<Path>) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(Self::new(BufWriter::new(file)))
+	}
+}
+
+impl<W: Write> GameWriter<W> {
+	pub fn new(output: W) -> Self {
+		Self { output }
+	}
+
+	/// Fails with [`io::ErrorKind::InvalidInput`], writing nothing, if the record wouldn't read back
+	/// as `game`, because of a header value with a newline or a comment with a `}` for example
+	pub fn write(&mut self, game: &Game) -> io::Result<()> {
+		if let Some(problem) = game.unwritable() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, problem));
+		}
+		writeln!(self.output, "{game}")
+	}
+
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.output.flush()
+	}
+
+	pub fn into_inner(self) -> W {
+		self.output
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::{self, GameResult, Move, MoveErr, State};
+
+	fn game(moves: &[&str], result: GameResult) -> Game {
+		let mut game = Game::new(State::default());
+		for mv in moves {
+			game.push(mv.parse::<Move>().unwrap()).unwrap();
+		}
+		game.result = result;
+		game
+	}
+
+	#[test]
+	fn reads_back_what_was_written() {
+		let games = [
+			game(&["e5", "e1"], GameResult::Ongoing),
+			game(&[], GameResult::Draw),
+			game(&["a1", "a2", "b1"], GameResult::XWins),
+		];
+
+		let mut writer = GameWriter::new(vec![]);
+		for game in &games {
+			writer.write(game).unwrap();
+		}
+		let text = writer.into_inner();
+
+		let read: Vec<_> = GameReader::new(&text[..])
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(read, games);
+	}
+
+	#[test]
+	fn keeps_blank_lines_in_comments() {
+		let mut first = game(&["e5", "e1"], GameResult::Ongoing);
+		first.comment = Some("before\n\nnot [a header]\n[X \"nor this\"]\n\nafter".into());
+		first.moves[1].comment = Some("one\n\ntwo".into());
+		let games = [first, game(&["a1"], GameResult::Ongoing)];
+
+		let mut writer = GameWriter::new(vec![]);
+		for game in &games {
+			writer.write(game).unwrap();
+		}
+		let text = writer.into_inner();
+
+		let read: Vec<_> = GameReader::new(&text[..])
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(read, games);
+	}
+
+	#[test]
+	fn refuses_games_that_would_not_read_back() {
+		let mut header = game(&["e5"], GameResult::Ongoing);
+		assert!(header.set_header("Event", "a\nb").is_err());
+		header.headers.push(("Event".into(), "a\nb".into()));
+		let mut comment = game(&["e5"], GameResult::Ongoing);
+		comment.moves[0].comment = Some("x}y".into());
+		let mut record = game(&["e5"], GameResult::Ongoing);
+		record.comment = Some("x\n\n[Event \"y\"]".into());
+		let fine = game(&["e5", "e1"], GameResult::Ongoing);
+
+		let mut writer = GameWriter::new(vec![]);
+		for bad in [&header, &comment, &record] {
+			let err = writer.write(bad).unwrap_err();
+			assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+		}
+		writer.write(&fine).unwrap();
+		writer.write(&fine).unwrap();
+
+		let text = writer.into_inner();
+		let read: Vec<_> = GameReader::new(&text[..])
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(read, [fine.clone(), fine]);
+	}
+
+	#[test]
+	fn reports_errors_and_continues() {
+		let text = "\n\n[X \"A\"]\n\n1. e5 e1 *\n\
+			[X \"B\"]\n1. e5\n   a1 *\n\n\n\
+			1. a1 *\n\
+			[X \"C\"]\n1. e0 *\n\
+			[Variant \"misere\"]\n*\n";
+
+		let results: Vec<_> = GameReader::new(text.as_bytes()).collect();
+		assert_eq!(results.len(), 5);
+		assert_eq!(results[0].as_ref().unwrap().header("X"), Some("A"));
+		assert_eq!(results[2].as_ref().unwrap().moves.len(), 1);
+
+		let errors: Vec<_> = results
+			.iter()
+			.filter_map(|res| match res {
+				Err(ReadError::Parse {
+					record,
+					line,
+					column,
+					err,
+				}) => Some((*record, *line, *column, err.clone())),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(errors.len(), 3);
+		assert_eq!(
+			errors[0],
+			(
+				6,
+				8,
+				4,
+				ParseError::Syntax(state::ParseError::InvalidMove {
+					err: MoveErr::Illegal,
+					span: 17..19
+				})
+			)
+		);
+		assert_eq!((errors[1].0, errors[1].1, errors[1].2), (12, 13, 4));
+		assert!(matches!(
+			errors[1].3,
+			ParseError::Syntax(state::ParseError::InvalidMove {
+				err: MoveErr::InvalidCell,
+				..
+			})
+		));
+		assert_eq!((errors[2].0, errors[2].1), (14, 14));
+		assert!(matches!(errors[2].3, ParseError::Variant { .. }));
+	}
+
+	#[test]
+	fn unclosed_comments_spoil_one_record() {
+		let text = "[X \"A\"]\n\n1. e5 {oops e1 *\n\n\
+			[X \"B\"]\n\n1. e5 e1 *\n\n\
+			[X \"C\"]\n\n1. a1 *\n\n\
+			[X \"D\"]\n\n1. e5 {fine} e1 *\n";
+
+		let results: Vec<_> = GameReader::new(text.as_bytes()).collect();
+		assert_eq!(results.len(), 4);
+		assert!(matches!(
+			results[0],
+			Err(ReadError::Parse { record: 1, .. })
+		));
+
+		let names: Vec<_> = results[1..]
+			.iter()
+			.map(|res| res.as_ref().unwrap().header("X").unwrap())
+			.collect();
+		assert_eq!(names, ["B", "C", "D"]);
+	}
+
+	#[test]
+	fn stops_after_io_errors() {
+		struct Failing;
+
+		impl io::Read for Failing {
+			fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+				Err(io::ErrorKind::BrokenPipe.into())
+			}
+		}
+
+		let mut reader = GameReader::new(BufReader::new(Failing));
+		assert!(matches!(reader.next(), Some(Err(ReadError::Io(_)))));
+		assert!(reader.next().is_none());
+	}
+
+	#[test]
+	fn appends_to_files() {
+		let path = std::env::temp_dir().join(format!("uttprotocol-{}.utg", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+
+		for moves in [&["e5"][..], &["a1", "a2"]] {
+			let mut writer = GameWriter::append_to(&path).unwrap();
+			writer.write(&game(moves, GameResult::Ongoing)).unwrap();
+			writer.flush().unwrap();
+		}
+
+		let lens: Vec<_> = GameReader::open(&path)
+			.unwrap()
+			.map(|game| game.unwrap().moves.len())
+			.collect();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(lens, [1, 2]);
+	}
+}
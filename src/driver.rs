@@ -0,0 +1,340 @@
+//! Engine side of the [`protocol`](crate::protocol).
+//!
+//! [`EngineDriver`] reads [`GuiCommand`]s line by line, handles the handshake itself and hands
+//! everything else to an [`Engine`]. Searches run on a worker thread, so `stop`, `isready` and
+//! `quit` are answered while the engine is thinking. `stop` reaches the engine through the flag
+//! passed to [`Engine::go`], which the engine should check regularly.
+
+use std::{
+	io::{self, BufRead, Write},
+	sync::{
+		Mutex,
+		atomic::{AtomicBool, Ordering},
+	},
+	thread::{self, ScopedJoinHandle},
+};
+
+use crate::{
+	protocol::{EngineCommand, EngineOption, GoParams, GuiCommand, IdField, Info},
+	state::{Move, State},
+};
+
+pub trait Engine: Send {
+	fn name(&self) -> &str;
+
+	fn author(&self) -> &str;
+
+	/// Options announced during the handshake
+	fn options(&self) -> Vec<EngineOption> {
+		vec![]
+	}
+
+	/// Called for `setoption`, `value` is `None` for buttons
+	fn set_option(&mut self, name: &str, value: Option<&str>);
+
+	/// Called for `newgame`, e.g. to clear hash tables
+	fn new_game(&mut self) {}
+
+	fn set_position(&mut self, state: State);
+
+	/// Searches the last position set, returning the move to play or `None` when there is no legal
+	/// move. The search should return as soon as possible once `stop` is set, and may report
+	/// progress through `info`.
+	fn go(
+		&mut self,
+		params: &GoParams,
+		stop: &AtomicBool,
+		info: &mut dyn FnMut(Info),
+	) -> Option<Move>;
+}
+
+pub struct EngineDriver<E> {
+	/// `None` while a search is running on the worker thread
+	engine: Option<E>,
+	stop: AtomicBool,
+}
+
+fn send<W: Write>(output: &Mutex<W>, cmd: &EngineCommand) -> io::Result<()> {
+	let mut output = output.lock().unwrap();
+	writeln!(output, "{cmd}")?;
+	output.flush()
+}
+
+impl<E: Engine> EngineDriver<E> {
+	pub fn new(engine: E) -> Self {
+		Self {
+			engine: Some(engine),
+			stop: AtomicBool::new(false),
+		}
+	}
+
+	pub fn into_engine(self) -> E {
+		self.engine.unwrap()
+	}
+
+	/// Handles commands from `input` until `quit` or the end of the input. Lines that fail to parse
+	/// are answered with an `info string` describing the error. Commands other than `stop`,
+	/// `isready` and `quit` that arrive during a search wait for it to finish.
+	pub fn run<R: BufRead, W: Write + Send>(&mut self, input: R, output: W) -> io::Result<()> {
+		let output = Mutex::new(output);
+		let (stop, slot) = (&self.stop, &mut self.engine);
+
+		thread::scope(|scope| {
+			let mut search: Option<ScopedJoinHandle<(E, io::Result<()>)>> = None;
+
+			// Whatever ends the loop, errors included, the search is stopped and joined below
+			let res = (|| {
+				for line in input.lines() {
+					let line = line?;
+					if line.trim().is_empty() {
+						continue;
+					}
+
+					let cmd = match line.parse::<GuiCommand>() {
+						Ok(cmd) => cmd,
+						Err(err) => {
+							send(&output, &error_info(format!("{err} in {line:?}")))?;
+							continue;
+						}
+					};
+
+					match cmd {
+						GuiCommand::IsReady => send(&output, &EngineCommand::ReadyOk)?,
+						GuiCommand::Stop => {
+							stop.store(true, Ordering::Relaxed);
+							finish(&mut search, slot)?;
+						}
+						GuiCommand::Quit => break,
+						cmd => {
+							finish(&mut search, slot)?;
+							let engine = slot.as_mut().unwrap();
+
+							match cmd {
+								GuiCommand::Utp => {
+									let mut replies = vec![
+										EngineCommand::Id {
+											field: IdField::Name,
+											value: engine.name().to_string(),
+										},
+										EngineCommand::Id {
+											field: IdField::Author,
+											value: engine.author().to_string(),
+										},
+									];
+									replies.extend(
+										engine.options().into_iter().map(EngineCommand::Option),
+									);
+									replies.push(EngineCommand::UtpOk);
+
+									for reply in &replies {
+										send(&output, reply)?;
+									}
+								}
+								GuiCommand::SetOption { name, value } => {
+									engine.set_option(&name, value.as_deref())
+								}
+								GuiCommand::NewGame => engine.new_game(),
+								GuiCommand::Position { .. } => match cmd.position().unwrap() {
+									Ok(state) => engine.set_position(state),
+									Err(err) => {
+										send(&output, &error_info(format!("{err} in {line:?}")))?
+									}
+								},
+								GuiCommand::Go(params) => {
+									let mut engine = slot.take().unwrap();
+									let output = &output;
+									stop.store(false, Ordering::Relaxed);
+
+									search = Some(scope.spawn(move || {
+										let mut res = Ok(());
+										let best = engine.go(&params, stop, &mut |info| {
+											if res.is_ok() {
+												res = send(output, &EngineCommand::Info(info));
+											}
+										});
+
+										let res = res.and_then(|_| {
+											send(output, &EngineCommand::BestMove(best))
+										});
+										(engine, res)
+									}));
+								}
+								GuiCommand::IsReady | GuiCommand::Stop | GuiCommand::Quit => {
+									unreachable!()
+								}
+							}
+						}
+					}
+				}
+
+				Ok(())
+			})();
+
+			stop.store(true, Ordering::Relaxed);
+			let finished = finish(&mut search, slot);
+			res.and(finished)
+		})
+	}
+}
+
+/// Waits for the running search, if any, and takes the engine back
+fn finish<E>(
+	search: &mut Option<ScopedJoinHandle<(E, io::Result<()>)>>,
+	engine: &mut Option<E>,
+) -> io::Result<()> {
+	if let Some(handle) = search.take() {
+		let (searched, res) = handle.join().unwrap();
+		*engine = Some(searched);
+		res?;
+	}
+	Ok(())
+}
+
+fn error_info(msg: String) -> EngineCommand {
+	EngineCommand::Info(Info {
+		string: Some(format!("error: {msg}")),
+		..Info::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Plays the first legal move, searching "forever" with `go infinite` until stopped
+	struct FirstMove {
+		state: State,
+		hash: String,
+	}
+
+	impl Engine for FirstMove {
+		fn name(&self) -> &str {
+			"First Move"
+		}
+
+		fn author(&self) -> &str {
+			"Tests"
+		}
+
+		fn options(&self) -> Vec<EngineOption> {
+			vec![EngineOption {
+				name: "Hash".into(),
+				kind: crate::protocol::OptionKind::Spin {
+					default: 16,
+					min: 1,
+					max: 64,
+				},
+			}]
+		}
+
+		fn set_option(&mut self, name: &str, value: Option<&str>) {
+			self.hash = format!("{name}={}", value.unwrap_or_default());
+		}
+
+		fn set_position(&mut self, state: State) {
+			self.state = state;
+		}
+
+		fn go(
+			&mut self,
+			params: &GoParams,
+			stop: &AtomicBool,
+			info: &mut dyn FnMut(Info),
+		) -> Option<Move> {
+			while params.infinite && !stop.load(Ordering::Relaxed) {
+				thread::yield_now();
+			}
+
+			info(Info {
+				depth: Some(1),
+				..Info::default()
+			});
+			self.state.legal_moves().next()
+		}
+	}
+
+	fn run(input: &str) -> (Vec<String>, FirstMove) {
+		let mut driver = EngineDriver::new(FirstMove {
+			state: State::default(),
+			hash: String::new(),
+		});
+		let mut output = vec![];
+		driver.run(input.as_bytes(), &mut output).unwrap();
+
+		let lines = String::from_utf8(output)
+			.unwrap()
+			.lines()
+			.map(str::to_string)
+			.collect();
+		(lines, driver.into_engine())
+	}
+
+	#[test]
+	fn handshake_and_search() {
+		let (lines, engine) = run("utp\n\
+			setoption name Hash value 32\n\
+			isready\n\
+			position startpos moves e5\n\
+			go depth 1\n\
+			quit\n\
+			go\n");
+
+		assert_eq!(
+			lines,
+			[
+				"id name First Move",
+				"id author Tests",
+				"option name Hash type spin default 16 min 1 max 64",
+				"utpok",
+				"readyok",
+				"info depth 1",
+				"bestmove e1",
+			]
+		);
+		assert_eq!(engine.hash, "Hash=32");
+	}
+
+	#[test]
+	fn stop_ends_an_infinite_search() {
+		let (lines, _) = run(
+			"position startpos\ngo infinite\nisready\nstop\nposition startpos moves a1\ngo infinite\n",
+		);
+
+		assert_eq!(
+			lines,
+			[
+				"readyok",
+				"info depth 1",
+				"bestmove a1",
+				"info depth 1",
+				"bestmove a2"
+			]
+		);
+	}
+
+	#[test]
+	fn read_errors_stop_the_search() {
+		let mut driver = EngineDriver::new(FirstMove {
+			state: State::default(),
+			hash: String::new(),
+		});
+		let mut output = vec![];
+		let input: &[u8] = b"position startpos\ngo infinite\n\xff\xfe\n";
+		let err = driver.run(input, &mut output).unwrap_err();
+
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		assert_eq!(output, b"info depth 1\nbestmove a1\n");
+	}
+
+	#[test]
+	fn reports_bad_lines() {
+		let (lines, _) = run("dance\nposition startpos moves e5 a1\n");
+
+		assert_eq!(lines.len(), 2);
+		assert!(
+			lines
+				.iter()
+				.all(|line| line.starts_with("info string error: "))
+		);
+	}
+}
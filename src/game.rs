@@ -0,0 +1,655 @@
+//! Game records, the whole history of a game where a UTT string only holds one position.
+//!
+//! The format is modelled on PGN. Headers come first, one per line as `[Key "value"]` with `\"`
+//! and `\\` escaped and no control characters, and are followed by the moves:
+//!
+//! ```text
+//! [X "Alpha"]
+//! [O "Beta"]
+//! [Date "2024.05.01"]
+//! [TimeControl "100ms"]
+//! [Variant "standard"]
+//! [Result "1-0"]
+//!
+//! {Both engines out of book} 1. e5 e1! 2. a5 {only move} e2?! 3. b5 e3 1-0
+//! ```
+//!
+//! Two headers are special: `Start` holds the starting position as a UTT string when it isn't the
+//! empty board, and `Result` must agree with the result written after the moves. `Variant`, if
+//! present, must be `standard`. Moves are in `e5` notation, optionally preceded by a move number
+//! (`1.`, or `1...` for a move by O) and followed by an [`Annotation`] and a `{comment}`. A comment
+//! before the first move belongs to the game. Parsing replays every move, so a [`Game`] only holds
+//! legal moves, and a game that is over by the rules must record that result.
+
+use std::{error::Error, fmt, ops::Range, str::FromStr};
+
+use chumsky::prelude::*;
+
+use crate::state::{self, GameResult, Move, MoveErr, Square, State, state_parser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Annotation {
+	/// `!`
+	Good,
+	/// `?`
+	Mistake,
+	/// `!!`
+	Brilliant,
+	/// `??`
+	Blunder,
+	/// `!?`
+	Interesting,
+	/// `?!`
+	Dubious,
+}
+
+impl fmt::Display for Annotation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Annotation::Good => "!",
+			Annotation::Mistake => "?",
+			Annotation::Brilliant => "!!",
+			Annotation::Blunder => "??",
+			Annotation::Interesting => "!?",
+			Annotation::Dubious => "?!",
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameMove {
+	pub mv: Move,
+	pub annotation: Option<Annotation>,
+	/// Must not contain `}`, nor a line starting with `[` after a blank line, which a
+	/// [`GameReader`](crate::database::GameReader) takes for the next record
+	pub comment: Option<String>,
+}
+
+impl From<Move> for GameMove {
+	fn from(mv: Move) -> Self {
+		Self {
+			mv,
+			annotation: None,
+			comment: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Game {
+	/// Headers other than `Start` and `Result`, in order, as [`Game::set_header`] accepts them
+	pub headers: Vec<(String, String)>,
+	pub start: State,
+	pub moves: Vec<GameMove>,
+	/// The recorded result, which can differ from the final position's when a game was
+	/// adjudicated or abandoned
+	pub result: GameResult,
+	/// Comment before the first move, restricted like [`GameMove::comment`]
+	pub comment: Option<String>,
+}
+
+impl Default for Game {
+	fn default() -> Self {
+		Self::new(State::default())
+	}
+}
+
+impl Game {
+	pub fn new(start: State) -> Self {
+		Self {
+			headers: vec![],
+			start,
+			moves: vec![],
+			result: GameResult::Ongoing,
+			comment: None,
+		}
+	}
+
+	pub fn header(&self, key: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(k, _)| k == key)
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// Replaces the value of `key`, or adds it after the other headers. `Start` and `Result` set
+	/// [`Game::start`] and [`Game::result`] instead, from the values they take in a record.
+	pub fn set_header(&mut self, key: &str, value: impl Into<String>) -> Result<(), HeaderError> {
+		let value = value.into();
+		if value.chars().any(char::is_control) {
+			return Err(HeaderError::Value);
+		}
+
+		match key {
+			"Start" => self.start = state::parse(&value).map_err(HeaderError::Start)?,
+			"Result" => {
+				self.result = result()
+					.then_ignore(end())
+					.parse(value.as_str())
+					.into_output()
+					.ok_or(HeaderError::Result)?
+			}
+			_ if !is_key(key) => return Err(HeaderError::Key),
+			_ => match self.headers.iter_mut().find(|(k, _)| k == key) {
+				Some((_, v)) => *v = value,
+				None => self.headers.push((key.to_string(), value)),
+			},
+		}
+		Ok(())
+	}
+
+	/// Why the record of this game wouldn't read back as the same game, if it wouldn't. The fields
+	/// are public, so only [`Game::set_header`] keeps their headers and comments in check.
+	pub(crate) fn unwritable(&self) -> Option<&'static str> {
+		let comment_ok = |comment: &Option<String>| {
+			comment.as_deref().is_none_or(|comment| {
+				let lines: Vec<_> = comment.lines().map(str::trim).collect();
+				!comment.contains('}')
+					&& !lines
+						.windows(2)
+						.any(|pair| pair[0].is_empty() && pair[1].starts_with('['))
+			})
+		};
+
+		if !self
+			.headers
+			.iter()
+			.all(|(key, _)| is_key(key) && key != "Start" && key != "Result")
+		{
+			Some("header keys must be identifiers other than Start and Result")
+		} else if self
+			.headers
+			.iter()
+			.any(|(_, value)| value.chars().any(char::is_control))
+		{
+			Some("header values must not hold control characters")
+		} else if !comment_ok(&self.comment) || !self.moves.iter().all(|mv| comment_ok(&mv.comment))
+		{
+			Some("comments must not hold `}` or start a line with `[` after a blank one")
+		} else {
+			None
+		}
+	}
+
+	/// Plays every move from the start, returning the final position, or the index of the first
+	/// move that can't be played and why
+	pub fn replay(&self) -> Result<State, (usize, MoveErr)> {
+		let mut state = self.start.clone();
+		for (i, mv) in self.moves.iter().enumerate() {
+			state.play(mv.mv).map_err(|err| (i, err))?;
+		}
+		Ok(state)
+	}
+
+	/// Plays `mv` after the last move, fails if it's illegal there
+	pub fn push(&mut self, mv: impl Into<GameMove>) -> Result<(), MoveErr> {
+		let mv = mv.into();
+		let mut state = self.replay().map_err(|(_, err)| err)?;
+		state.play(mv.mv)?;
+		self.moves.push(mv);
+		Ok(())
+	}
+}
+
+fn is_key(key: &str) -> bool {
+	text::ident::<_, Extra>()
+		.then_ignore(end())
+		.parse(key)
+		.has_output()
+}
+
+/// Why [`Game::set_header`] refused a header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+	/// Keys must be identifiers, such as `TimeControl`
+	Key,
+	/// Values can't hold control characters such as newlines
+	Value,
+	/// `Start` must be a UTT string
+	Start(state::ParseError),
+	/// `Result` must be `1-0`, `0-1`, `1/2-1/2` or `*`
+	Result,
+}
+
+impl fmt::Display for HeaderError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			HeaderError::Key => write!(f, "header keys must be identifiers"),
+			HeaderError::Value => write!(f, "header values must not hold control characters"),
+			HeaderError::Start(err) => write!(f, "invalid start position: {err}"),
+			HeaderError::Result => write!(f, "invalid result"),
+		}
+	}
+}
+
+impl Error for HeaderError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			HeaderError::Start(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+/// Escapes `\` and `"` for a quoted string
+pub(crate) fn escape(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Lines of movetext are wrapped before this many columns
+const WIDTH: usize = 80;
+
+impl fmt::Display for Game {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (key, value) in &self.headers {
+			writeln!(f, "[{key} \"{}\"]", escape(value))?;
+		}
+		writeln!(f, "[Result \"{}\"]", self.result)?;
+		if self.start != State::default() {
+			writeln!(f, "[Start \"{}\"]", self.start)?;
+		}
+		writeln!(f)?;
+
+		let mut tokens = vec![];
+		if let Some(comment) = &self.comment {
+			tokens.push(format!("{{{comment}}}"));
+		}
+
+		let mut number = 1;
+		let mut side = self.start.side_to_move();
+		for (i, mv) in self.moves.iter().enumerate() {
+			if side == Square::X {
+				tokens.push(format!("{number}."));
+			} else if i == 0 {
+				tokens.push(format!("{number}..."));
+			}
+
+			match mv.annotation {
+				Some(annotation) => tokens.push(format!("{}{annotation}", mv.mv)),
+				None => tokens.push(mv.mv.to_string()),
+			}
+			if let Some(comment) = &mv.comment {
+				tokens.push(format!("{{{comment}}}"));
+			}
+
+			if side == Square::O {
+				number += 1;
+				side = Square::X;
+			} else {
+				side = Square::O;
+			}
+		}
+		tokens.push(self.result.to_string());
+
+		let mut len = 0;
+		for token in tokens {
+			if len > 0 && len + 1 + token.len() >= WIDTH {
+				writeln!(f)?;
+				len = 0;
+			} else if len > 0 {
+				write!(f, " ")?;
+				len += 1;
+			}
+
+			write!(f, "{token}")?;
+			len += token.len();
+		}
+		writeln!(f)
+	}
+}
+
+/// Error produced when a game record fails to parse, spans are byte offsets into the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+	/// The record is malformed, or holds an invalid position or move
+	Syntax(state::ParseError),
+	/// The record is for rules other than the standard ones
+	Variant { span: Range<usize> },
+	/// The result contradicts the `Result` header or the final position
+	Result { span: Range<usize> },
+}
+
+impl ParseError {
+	pub fn span(&self) -> Range<usize> {
+		match self {
+			Self::Syntax(err) => err.span(),
+			Self::Variant { span } | Self::Result { span } => span.clone(),
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Range { start, end } = self.span();
+
+		match self {
+			Self::Syntax(err) => write!(f, "{err}"),
+			Self::Variant { .. } => write!(f, "unsupported variant at {start}..{end}"),
+			Self::Result { .. } => write!(f, "inconsistent result at {start}..{end}"),
+		}
+	}
+}
+
+impl Error for ParseError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Syntax(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<state::ParseError> for ParseError {
+	fn from(err: state::ParseError) -> Self {
+		Self::Syntax(err)
+	}
+}
+
+type Extra = extra::Err<state::ParseError>;
+
+enum Header {
+	Start(State),
+	Result(GameResult),
+	Other(String, String, Range<usize>),
+}
+
+pub(crate) fn result<'a>() -> impl Parser<'a, &'a str, GameResult, Extra> + Clone {
+	choice((
+		just("1-0").to(GameResult::XWins),
+		just("0-1").to(GameResult::OWins),
+		just("1/2-1/2").to(GameResult::Draw),
+		just('*').to(GameResult::Ongoing),
+	))
+}
+
+/// A quoted string with `\"` and `\\` escaped, the inverse of [`escape`]
+pub(crate) fn string<'a>() -> impl Parser<'a, &'a str, String, Extra> + Clone {
+	none_of("\\\"")
+		.or(just('\\').ignore_then(one_of("\\\"")))
+		.repeated()
+		.collect::<String>()
+		.delimited_by(just('"'), just('"'))
+}
+
+/// A move in `e5` notation, a letter and a digit so that it can be followed by punctuation
+pub(crate) fn mv<'a>() -> impl Parser<'a, &'a str, Move, Extra> + Clone {
+	one_of('a'..='z')
+		.then(one_of('0'..='9'))
+		.to_slice()
+		.try_map(|s: &str, span: SimpleSpan| {
+			s.parse::<Move>()
+				.map_err(|err| state::ParseError::InvalidMove {
+					err,
+					span: span.into_range(),
+				})
+		})
+}
+
+/// A game record without the end of input, so it can be embedded in other grammars. Syntax errors
+/// fail the parser, while a well formed record that breaks the rules is parsed into an `Err`.
+pub(crate) fn game_parser<'a>() -> impl Parser<'a, &'a str, Result<Game, ParseError>, Extra> + Clone
+{
+	let quote = || just('"');
+	let key = |name| text::keyword(name).then(text::inline_whitespace().at_least(1));
+	let header = choice((
+		key("Start").ignore_then(
+			state_parser()
+				.delimited_by(quote(), quote())
+				.map(Header::Start),
+		),
+		key("Result").ignore_then(result().delimited_by(quote(), quote()).map(Header::Result)),
+		text::ident()
+			.filter(|key: &&str| !["Start", "Result"].contains(key))
+			.then_ignore(text::inline_whitespace().at_least(1))
+			.then(string().map_with(|value, e| (value, e.span())))
+			.map(|(key, (value, span)): (&str, (String, SimpleSpan))| {
+				Header::Other(key.to_string(), value, span.into_range())
+			}),
+	))
+	.padded_by(text::inline_whitespace())
+	.delimited_by(just('['), just(']'));
+
+	let comment = none_of('}')
+		.repeated()
+		.to_slice()
+		.delimited_by(just('{'), just('}'))
+		.map(|s: &str| s.trim().to_string());
+	let number = text::int(10).then(choice((just("..."), just("."))));
+	let annotation = choice((
+		just("!!").to(Annotation::Brilliant),
+		just("??").to(Annotation::Blunder),
+		just("!?").to(Annotation::Interesting),
+		just("?!").to(Annotation::Dubious),
+		just('!').to(Annotation::Good),
+		just('?').to(Annotation::Mistake),
+	));
+	let game_move = number
+		.then(text::whitespace())
+		.or_not()
+		.ignore_then(mv().map_with(|mv, e| (mv, SimpleSpan::into_range(e.span()))))
+		.then(annotation.or_not())
+		.then_ignore(text::whitespace())
+		.then(comment.then_ignore(text::whitespace()).or_not())
+		.map(|(((mv, span), annotation), comment)| {
+			(
+				GameMove {
+					mv,
+					annotation,
+					comment,
+				},
+				span,
+			)
+		});
+
+	text::whitespace()
+		.ignore_then(
+			header
+				.then_ignore(text::whitespace())
+				.repeated()
+				.collect::<Vec<_>>(),
+		)
+		.then(comment.then_ignore(text::whitespace()).or_not())
+		.then(game_move.repeated().collect::<Vec<_>>())
+		.then(result().map_with(|result, e| (result, e.span())))
+		.try_map(|(((headers, comment), moves), (result, span)), _| {
+			let span: Range<usize> = SimpleSpan::into_range(span);
+			let mut game = Game {
+				comment,
+				result,
+				..Game::default()
+			};
+
+			let mut header_result = None;
+			let mut variant = None;
+			for header in headers {
+				match header {
+					Header::Start(start) => game.start = start,
+					Header::Result(result) => header_result = Some(result),
+					Header::Other(key, value, span) => {
+						if key == "Variant" && value != "standard" {
+							variant.get_or_insert(span);
+						}
+						game.headers.push((key, value));
+					}
+				}
+			}
+
+			let mut state = game.start.clone();
+			for (mv, span) in moves {
+				state
+					.play(mv.mv)
+					.map_err(|err| state::ParseError::InvalidMove { err, span })?;
+				game.moves.push(mv);
+			}
+
+			if let Some(span) = variant {
+				return Ok(Err(ParseError::Variant { span }));
+			}
+			let decided = state.result() != GameResult::Ongoing && state.result() != result;
+			if decided || header_result.is_some_and(|header| header != result) {
+				return Ok(Err(ParseError::Result { span }));
+			}
+
+			Ok(Ok(game))
+		})
+}
+
+fn _parse<'a>() -> impl Parser<'a, &'a str, Result<Game, ParseError>, Extra> {
+	game_parser().then_ignore(text::whitespace()).then_ignore(
+		end().map_err(|e: state::ParseError| state::ParseError::TrailingInput { span: e.span() }),
+	)
+}
+
+/// Parses a single game record, see the module documentation for the format
+pub fn parse(input: &str) -> Result<Game, ParseError> {
+	_parse()
+		.parse(input)
+		.into_result()
+		.map_err(|errs| ParseError::Syntax(errs.into_iter().next().unwrap()))?
+}
+
+impl FromStr for Game {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		parse(s)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mv(s: &str) -> Move {
+		s.parse().unwrap()
+	}
+
+	const RECORD: &str = "[X \"Alpha\"]
+[O \"Beta \\\"the second\\\"\"]
+[Variant \"standard\"]
+[Result \"1-0\"]
+
+{Both out of book} 1. e5 e1! 2. a5 {only move} e2?! 3. b5 e3 1-0
+";
+
+	#[test]
+	fn parses_records() {
+		let game: Game = RECORD.parse().unwrap();
+
+		assert_eq!(game.header("X"), Some("Alpha"));
+		assert_eq!(game.header("O"), Some("Beta \"the second\""));
+		assert_eq!(game.header("Result"), None);
+		assert_eq!(game.result, GameResult::XWins);
+		assert_eq!(game.comment.as_deref(), Some("Both out of book"));
+		assert_eq!(game.start, State::default());
+
+		let moves: Vec<_> = game.moves.iter().map(|mv| mv.mv.to_string()).collect();
+		assert_eq!(moves, ["e5", "e1", "a5", "e2", "b5", "e3"]);
+		assert_eq!(game.moves[1].annotation, Some(Annotation::Good));
+		assert_eq!(game.moves[2].comment.as_deref(), Some("only move"));
+		assert_eq!(game.moves[3].annotation, Some(Annotation::Dubious));
+
+		// Numbers are optional and whitespace is free
+		let bare: Game = "e5 e1!\n\n a5{only move}e2?!b5 e3 1-0".parse().unwrap();
+		assert_eq!(bare.moves, game.moves[..]);
+		assert_eq!(
+			bare.to_string(),
+			"[Result \"1-0\"]\n\n1. e5 e1! 2. a5 {only move} e2?! 3. b5 e3 1-0\n"
+		);
+	}
+
+	#[test]
+	fn round_trips() {
+		let game: Game = RECORD.parse().unwrap();
+		assert_eq!(game.to_string(), RECORD);
+
+		// Starting with O to move, and enough moves to wrap
+		let mut start = State::default();
+		start.play(mv("e5")).unwrap();
+		let mut game = Game::new(start);
+		game.set_header("Event", "Test").unwrap();
+		let mut state = game.start.clone();
+		while state.result() == GameResult::Ongoing {
+			let mv = state.legal_moves().next().unwrap();
+			state.play(mv).unwrap();
+			game.push(mv).unwrap();
+		}
+		game.result = state.result();
+
+		let text = game.to_string();
+		assert!(
+			text.contains("[Start \"4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5\"]\n\n1... e1 2. a1 a2 3. ")
+		);
+		assert!(text.lines().all(|line| line.len() < WIDTH));
+		assert_eq!(text.parse::<Game>().unwrap(), game);
+	}
+
+	#[test]
+	fn sets_headers() {
+		let mut game = Game::default();
+		game.set_header("TimeControl", "100ms").unwrap();
+		game.set_header("TimeControl", "1s").unwrap();
+		game.set_header("Result", "1/2-1/2").unwrap();
+		game.set_header("Start", "4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5")
+			.unwrap();
+		assert_eq!(game.headers, [("TimeControl".into(), "1s".into())]);
+		assert_eq!(game.result, GameResult::Draw);
+		assert_eq!(game.start.last_move, Some(mv("e5")));
+
+		assert_eq!(game.set_header("Time Control", "1s"), Err(HeaderError::Key));
+		assert_eq!(game.set_header("", "1s"), Err(HeaderError::Key));
+		assert_eq!(game.set_header("Event", "a\nb"), Err(HeaderError::Value));
+		assert_eq!(game.set_header("Result", "2-0"), Err(HeaderError::Result));
+		assert!(matches!(
+			game.set_header("Start", "9/9"),
+			Err(HeaderError::Start(_))
+		));
+		assert_eq!(game.to_string().parse::<Game>().unwrap(), game);
+	}
+
+	#[test]
+	fn validates() {
+		let err = |s: &str| s.parse::<Game>().unwrap_err();
+
+		assert_eq!(
+			err("1. e5 a1 *"),
+			ParseError::Syntax(state::ParseError::InvalidMove {
+				err: MoveErr::Illegal,
+				span: 6..8
+			})
+		);
+		assert_eq!(
+			err("[Result \"0-1\"]\n1. e5 1-0"),
+			ParseError::Result { span: 21..24 }
+		);
+		assert_eq!(
+			err("[Variant \"misere\"]\n*"),
+			ParseError::Variant { span: 9..17 }
+		);
+		assert_eq!(
+			err("[Start \"9/9\"]\n*"),
+			ParseError::Syntax(state::ParseError::Run { span: 10..11 })
+		);
+		assert!(matches!(
+			err("1. e0 *"),
+			ParseError::Syntax(state::ParseError::InvalidMove {
+				err: MoveErr::InvalidCell,
+				..
+			})
+		));
+		assert!(matches!(
+			err("1. e5"),
+			ParseError::Syntax(state::ParseError::Unexpected { found: None, .. })
+		));
+		assert!(matches!(
+			err("* e5"),
+			ParseError::Syntax(state::ParseError::TrailingInput { .. })
+		));
+
+		let mut game = Game::default();
+		game.push(mv("e5")).unwrap();
+		assert_eq!(game.push(mv("e5")), Err(MoveErr::Illegal));
+		game.moves.push(mv("a1").into());
+		assert_eq!(game.replay(), Err((1, MoveErr::Illegal)));
+	}
+}
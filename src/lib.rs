@@ -1,4 +1,21 @@
+pub mod bitboard;
+pub mod database;
+pub mod driver;
+pub mod game;
+pub mod mcts;
+pub mod packed;
+pub mod perft;
+pub mod process;
+pub mod protocol;
+pub mod search;
 pub mod state;
+pub mod stats;
+pub mod suite;
+pub mod symmetry;
+#[cfg(test)]
+mod testing;
+pub mod tournament;
+pub mod zobrist;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
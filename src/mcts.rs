@@ -0,0 +1,355 @@
+//! Monte Carlo tree search with UCT
+//! Every iteration walks down the tree picking children by UCT, expands one untried move, plays
+//! random games from there on a [`BitState`] and adds their results to every node on the way back
+//! up. The tree is kept between searches, and is reused when the next search starts from a
+//! position already in it, e.g. after our move and the opponent's reply.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+	bitboard::BitState,
+	state::{GameResult, Move, Square, State},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+	/// Weight of the exploration term in UCT
+	pub exploration: f64,
+	/// Iterations to run per search
+	pub iterations: u64,
+	/// Random games played from every expanded node
+	pub playouts: u32,
+	pub seed: u64,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			exploration: std::f64::consts::SQRT_2,
+			iterations: 10_000,
+			playouts: 1,
+			seed: 0x6d63_7473,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MctsResult {
+	/// The most visited move, `None` when the game is already decided
+	pub best_move: Option<Move>,
+	/// Share of playouts through the best move won by the side to move, draws count as half
+	pub win_rate: f64,
+	/// Visits of the root, including ones from earlier searches that were reused
+	pub visits: u64,
+	pub iterations: u64,
+}
+
+/// xorshift64*, good enough for picking random moves
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+	fn below(&mut self, n: usize) -> usize {
+		self.0 ^= self.0 >> 12;
+		self.0 ^= self.0 << 25;
+		self.0 ^= self.0 >> 27;
+		(self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+	/// Move leading here from the parent, `None` for the root
+	mv: Option<Move>,
+	/// Side that played `mv`
+	mover: Square,
+	children: Vec<usize>,
+	untried: Vec<Move>,
+	visits: u64,
+	/// Sum of playout results from `mover`'s point of view
+	reward: f64,
+}
+
+impl Node {
+	fn new(mv: Option<Move>, state: &BitState) -> Self {
+		Self {
+			mv,
+			mover: match state.side_to_move() {
+				Square::X => Square::O,
+				_ => Square::X,
+			},
+			children: vec![],
+			untried: state.legal_moves().collect(),
+			visits: 0,
+			reward: 0.0,
+		}
+	}
+}
+
+pub struct Mcts {
+	config: Config,
+	rng: Rng,
+	/// Arena of nodes, the root is always at index 0
+	nodes: Vec<Node>,
+	root_state: Option<BitState>,
+}
+
+impl Mcts {
+	pub fn new(config: Config) -> Self {
+		Self {
+			rng: Rng(config.seed | 1),
+			config,
+			nodes: vec![],
+			root_state: None,
+		}
+	}
+
+	/// Throws away the tree, e.g. when a new game starts
+	pub fn clear(&mut self) {
+		self.nodes.clear();
+		self.root_state = None;
+	}
+
+	/// Number of nodes in the tree
+	pub fn tree_size(&self) -> usize {
+		self.nodes.len()
+	}
+
+	/// Runs up to [`Config::iterations`] iterations from `state`, stopping early once `stop` is set
+	pub fn search(&mut self, state: &State, stop: Option<&AtomicBool>) -> MctsResult {
+		let bits = BitState::from(state);
+		self.reroot(&bits);
+
+		let mut iterations = 0;
+		while iterations < self.config.iterations
+			&& !stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+		{
+			self.iterate(&bits);
+			iterations += 1;
+		}
+
+		let root = &self.nodes[0];
+		let best = root
+			.children
+			.iter()
+			.map(|&child| &self.nodes[child])
+			.max_by_key(|child| child.visits);
+
+		MctsResult {
+			// Without a single iteration any legal move will do
+			best_move: best
+				.and_then(|child| child.mv)
+				.or_else(|| root.untried.first().copied()),
+			win_rate: best.map_or(0.0, |child| child.reward / child.visits.max(1) as f64),
+			visits: root.visits,
+			iterations,
+		}
+	}
+
+	/// Makes the node for `bits` the root, keeping its subtree if it's at most 2 moves below the
+	/// current root and starting a new tree otherwise
+	fn reroot(&mut self, bits: &BitState) {
+		let mut found = None;
+
+		if let Some(root_state) = &self.root_state {
+			let nodes = &self.nodes;
+			let mut frontier = vec![(0, root_state.clone())];
+
+			for _ in 0..=2 {
+				if let Some((node, _)) = frontier.iter().find(|(_, state)| state == bits) {
+					found = Some(*node);
+					break;
+				}
+
+				frontier = frontier
+					.into_iter()
+					.flat_map(|(node, state)| {
+						nodes[node].children.iter().map(move |&child| {
+							let mut state = state.clone();
+							state.play(nodes[child].mv.unwrap()).unwrap();
+							(child, state)
+						})
+					})
+					.collect();
+			}
+		}
+
+		self.root_state = Some(bits.clone());
+		self.nodes = match found {
+			Some(0) => return,
+			Some(node) => self.extract(node),
+			None => vec![Node::new(None, bits)],
+		};
+	}
+
+	/// Copies the subtree under `node` into a fresh arena with `node` at index 0
+	fn extract(&self, node: usize) -> Vec<Node> {
+		let mut nodes = vec![self.nodes[node].clone()];
+		let mut i = 0;
+
+		while i < nodes.len() {
+			let children = std::mem::take(&mut nodes[i].children);
+			for child in children {
+				let new_index = nodes.len();
+				nodes.push(self.nodes[child].clone());
+				nodes[i].children.push(new_index);
+			}
+			i += 1;
+		}
+
+		nodes[0].mv = None;
+		nodes
+	}
+
+	fn iterate(&mut self, root_state: &BitState) {
+		let mut state = root_state.clone();
+		let mut path = vec![0];
+		let mut node = 0;
+
+		// Selection
+		while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+			node = self.select(node);
+			state.play(self.nodes[node].mv.unwrap()).unwrap();
+			path.push(node);
+		}
+
+		// Expansion
+		let untried = &mut self.nodes[node].untried;
+		if !untried.is_empty() {
+			let mv = untried.swap_remove(self.rng.below(untried.len()));
+			state.play(mv).unwrap();
+
+			let child = self.nodes.len();
+			self.nodes.push(Node::new(Some(mv), &state));
+			self.nodes[node].children.push(child);
+			path.push(child);
+		}
+
+		// Simulation, scored for X
+		let mut x_reward = 0.0;
+		for _ in 0..self.config.playouts {
+			x_reward += match self.playout(state.clone()) {
+				GameResult::XWins => 1.0,
+				GameResult::Draw => 0.5,
+				_ => 0.0,
+			};
+		}
+
+		// Backpropagation
+		let playouts = self.config.playouts as f64;
+		for node in path {
+			let node = &mut self.nodes[node];
+			node.visits += self.config.playouts as u64;
+			node.reward += match node.mover {
+				Square::X => x_reward,
+				_ => playouts - x_reward,
+			};
+		}
+	}
+
+	fn select(&self, node: usize) -> usize {
+		let parent = &self.nodes[node];
+		let log_visits = (parent.visits.max(1) as f64).ln();
+
+		let uct = |child: usize| {
+			let child = &self.nodes[child];
+			let visits = child.visits.max(1) as f64;
+			child.reward / visits + self.config.exploration * (log_visits / visits).sqrt()
+		};
+
+		parent
+			.children
+			.iter()
+			.copied()
+			.max_by(|&a, &b| uct(a).total_cmp(&uct(b)))
+			.unwrap()
+	}
+
+	fn playout(&mut self, mut state: BitState) -> GameResult {
+		loop {
+			let legal = state.legal_mask();
+			if legal == 0 {
+				return state.result();
+			}
+
+			// Pick the n-th set bit of the legal mask
+			let mut mask = legal;
+			for _ in 0..self.rng.below(legal.count_ones() as usize) {
+				mask &= mask - 1;
+			}
+
+			let mv = Move::from_index(mask.trailing_zeros() as usize).unwrap();
+			state.play(mv).unwrap();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::parse;
+
+	/// X to move can win the game by taking board c
+	const WINNING: &str =
+		"2/XO_OX_2OX/XO_OX2_2X/O2_X_X3_/OX2_2XO_X/2O2_OXOXO/_X3_X3O/2X_OX3_X/_2OX2OX_X/X3OXOXO_/h3";
+
+	#[test]
+	fn finds_the_win() {
+		let state = parse(WINNING).unwrap();
+		let mut mcts = Mcts::new(Config {
+			iterations: 2_000,
+			..Config::default()
+		});
+		let result = mcts.search(&state, None);
+
+		let mut after = state.clone();
+		after.play(result.best_move.unwrap()).unwrap();
+		assert_eq!(after.result(), GameResult::XWins);
+		assert!(result.win_rate > 0.9);
+		assert_eq!(result.iterations, 2_000);
+	}
+
+	#[test]
+	fn reuses_the_tree() {
+		let mut state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+		let mut mcts = Mcts::new(Config {
+			iterations: 3_000,
+			playouts: 2,
+			..Config::default()
+		});
+
+		let first = mcts.search(&state, None);
+		assert_eq!(first.visits, 6_000);
+		assert_eq!(mcts.tree_size(), 3_001);
+
+		// Our move and a reply later, the subtree below them is kept
+		state.play(first.best_move.unwrap()).unwrap();
+		let reply = state.legal_moves().next().unwrap();
+		state.play(reply).unwrap();
+		let second = mcts.search(&state, None);
+		assert!(second.visits > 6_000);
+		assert!(state.is_legal(second.best_move.unwrap()));
+
+		// An unrelated position starts over
+		let third = mcts.search(&parse(WINNING).unwrap(), None);
+		assert_eq!(third.visits, 6_000);
+	}
+
+	#[test]
+	fn stops_and_handles_decided_games() {
+		let state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+		let result = Mcts::new(Config::default()).search(&state, Some(&AtomicBool::new(true)));
+		assert_eq!(result.iterations, 0);
+		assert!(state.is_legal(result.best_move.unwrap()));
+		let config = Config {
+			iterations: 0,
+			..Config::default()
+		};
+		let result = Mcts::new(config).search(&state, None);
+		assert!(state.is_legal(result.best_move.unwrap()));
+
+		let state = parse("9/3O6_/3O6_/3O6_/9_/9_/9_/9_/9_/9_").unwrap();
+		let result = Mcts::new(Config::default()).search(&state, None);
+		assert_eq!(result.best_move, None);
+	}
+}
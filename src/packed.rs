@@ -0,0 +1,733 @@
+//! Fixed size binary encoding of positions, and a compact binary format for games built on it.
+//!
+//! A [`State`] packs into [`SIZE`] bytes, where its UTT string takes up to about 100. The first 16
+//! bytes hold squares 0..80 in base 3, 5 squares per byte with the lowest index in the least
+//! significant digit, where empty is 0, X is 1 and O is 2. The last 2 bytes are a little endian
+//! number holding the last square, the active board and the last move:
+//!
+//! ```text
+//! square 80 + 3 * (active + 10 * last move)
+//! ```
+//!
+//! where the last move is its [`Move::index`] plus 1, or 0 when there is none. No position needs
+//! fewer bytes, since there are more than 2^136 of them. [`to_base64`] writes the bytes in the URL
+//! safe base64 alphabet, which takes exactly 24 characters without padding.
+//!
+//! A [`PackedGame`] is written as a record of its own, so files of them can be appended to and
+//! concatenated. All numbers are little endian:
+//!
+//! ```text
+//! "UG" | version | flags | start | move count | moves | result | scores | checksum
+//! ```
+//!
+//! The version is 1, and bit 0 of the flags is set when there are scores. The start is an encoded
+//! position, each move one byte holding its [`Move::index`], and the result 0 for ongoing, 1 when
+//! X wins, 2 when O wins and 3 for a draw. Scores are 2 bytes per move, see [`PackedGame::scores`].
+//! The checksum is the CRC-32 of everything before it.
+
+use std::{
+	error::Error,
+	fmt,
+	fs::{File, OpenOptions},
+	io::{self, BufRead, BufReader, BufWriter, Read, Write},
+	path::Path,
+};
+
+use crate::{
+	game::{Game, GameMove},
+	protocol::Score,
+	state::{GameResult, Move, Square, State},
+};
+
+/// Length of an encoded position in bytes
+pub const SIZE: usize = 18;
+
+/// Length of a position encoded with [`to_base64`]
+pub const BASE64_SIZE: usize = SIZE / 3 * 4;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+	/// The input isn't [`SIZE`] bytes or [`BASE64_SIZE`] characters long
+	Length { found: usize },
+	/// The byte at `index` is out of range
+	InvalidByte { index: usize },
+	/// The character at `index` isn't in the URL safe base64 alphabet
+	InvalidChar { index: usize },
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DecodeError::Length { found } => write!(f, "wrong length {found}"),
+			DecodeError::InvalidByte { index } => write!(f, "byte {index} is out of range"),
+			DecodeError::InvalidChar { index } => write!(f, "invalid base64 at {index}"),
+		}
+	}
+}
+
+impl Error for DecodeError {}
+
+fn digit(sq: Square) -> u16 {
+	match sq {
+		Square::Empty => 0,
+		Square::X => 1,
+		Square::O => 2,
+	}
+}
+
+fn square(digit: u16) -> Square {
+	match digit {
+		0 => Square::Empty,
+		1 => Square::X,
+		_ => Square::O,
+	}
+}
+
+/// Packs `state`, whose active board must be 0..=9
+pub fn encode(state: &State) -> [u8; SIZE] {
+	assert!(
+		state.active <= 9,
+		"active board {} is above 9",
+		state.active
+	);
+
+	let mut bytes = [0; SIZE];
+	for (byte, squares) in bytes.iter_mut().zip(state.squares.chunks_exact(5)) {
+		*byte = squares
+			.iter()
+			.rev()
+			.fold(0, |acc, &sq| acc * 3 + digit(sq) as u8);
+	}
+
+	let last_move = state.last_move.map_or(0, |mv| mv.index() as u16 + 1);
+	let trailer = digit(state.squares[80]) + 3 * (state.active as u16 + 10 * last_move);
+	bytes[16..].copy_from_slice(&trailer.to_le_bytes());
+
+	bytes
+}
+
+/// Unpacks a position written by [`encode`]
+pub fn decode(bytes: &[u8]) -> Result<State, DecodeError> {
+	if bytes.len() != SIZE {
+		return Err(DecodeError::Length { found: bytes.len() });
+	}
+
+	let mut state = State::default();
+	for (index, (&byte, squares)) in bytes
+		.iter()
+		.zip(state.squares.chunks_exact_mut(5))
+		.enumerate()
+	{
+		if byte >= 243 {
+			return Err(DecodeError::InvalidByte { index });
+		}
+		let mut byte = byte as u16;
+		for sq in squares {
+			*sq = square(byte % 3);
+			byte /= 3;
+		}
+	}
+
+	let trailer = u16::from_le_bytes([bytes[16], bytes[17]]);
+	if trailer >= 3 * 10 * 82 {
+		return Err(DecodeError::InvalidByte { index: 17 });
+	}
+	state.squares[80] = square(trailer % 3);
+	state.active = (trailer / 3 % 10) as u8;
+	state.last_move = match trailer / 30 {
+		0 => None,
+		index => Move::from_index(index as usize - 1),
+	};
+
+	Ok(state)
+}
+
+/// [`encode`]s `state` in URL safe base64
+pub fn to_base64(state: &State) -> String {
+	encode(state)
+		.chunks_exact(3)
+		.flat_map(|chunk| {
+			let n = u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]);
+			[18, 12, 6, 0].map(|shift| ALPHABET[(n >> shift) as usize & 63] as char)
+		})
+		.collect()
+}
+
+/// Decodes a position written by [`to_base64`]
+pub fn from_base64(text: &str) -> Result<State, DecodeError> {
+	if text.len() != BASE64_SIZE {
+		return Err(DecodeError::Length { found: text.len() });
+	}
+
+	let mut bytes = [0; SIZE];
+	for (i, (chunk, out)) in text
+		.as_bytes()
+		.chunks_exact(4)
+		.zip(bytes.chunks_exact_mut(3))
+		.enumerate()
+	{
+		let mut n = 0;
+		for (j, &c) in chunk.iter().enumerate() {
+			let value = ALPHABET
+				.iter()
+				.position(|&a| a == c)
+				.ok_or(DecodeError::InvalidChar { index: 4 * i + j })?;
+			n = n << 6 | value as u32;
+		}
+		out.copy_from_slice(&n.to_be_bytes()[1..]);
+	}
+
+	decode(&bytes)
+}
+
+const MAGIC: [u8; 2] = *b"UG";
+const VERSION: u8 = 1;
+const HAS_SCORES: u8 = 1;
+
+const CRC_TABLE: [u32; 256] = {
+	let mut table = [0; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut crc = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 == 1 {
+				crc >> 1 ^ 0xedb8_8320
+			} else {
+				crc >> 1
+			};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+};
+
+/// The CRC-32 used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+	!bytes.iter().fold(!0, |crc, &byte| {
+		CRC_TABLE[(crc ^ byte as u32) as usize & 0xff] ^ crc >> 8
+	})
+}
+
+fn pack_score(score: Option<Score>) -> i16 {
+	match score {
+		None => i16::MIN,
+		Some(Score::Cp(n)) => n.clamp(-31999, 31999) as i16,
+		Some(Score::Win(n)) => i16::MAX - n.min(767) as i16,
+		Some(Score::Loss(n)) => -i16::MAX + n.min(767) as i16,
+	}
+}
+
+fn unpack_score(n: i16) -> Option<Score> {
+	match n {
+		i16::MIN => None,
+		32000.. => Some(Score::Win((i16::MAX - n) as u32)),
+		-32767..=-32000 => Some(Score::Loss((n + i16::MAX) as u32)),
+		n => Some(Score::Cp(n as i32)),
+	}
+}
+
+/// A game as stored for training, without the headers, comments and annotations of a [`Game`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedGame {
+	pub start: State,
+	pub moves: Vec<Move>,
+	pub result: GameResult,
+	/// Empty, or the score of every move from the point of view of the side that played it, `None`
+	/// where there wasn't one. Centi-board scores are stored within +/-31999, and wins and losses
+	/// within 767 plies.
+	pub scores: Vec<Option<Score>>,
+}
+
+impl PackedGame {
+	pub fn new(start: State) -> Self {
+		Self {
+			start,
+			moves: vec![],
+			result: GameResult::Ongoing,
+			scores: vec![],
+		}
+	}
+
+	/// The record of this game, see the module documentation
+	pub fn to_bytes(&self) -> Vec<u8> {
+		assert!(
+			self.scores.is_empty() || self.scores.len() == self.moves.len(),
+			"{} scores for {} moves",
+			self.scores.len(),
+			self.moves.len()
+		);
+
+		let mut bytes = MAGIC.to_vec();
+		bytes.push(VERSION);
+		bytes.push(if self.scores.is_empty() {
+			0
+		} else {
+			HAS_SCORES
+		});
+		bytes.extend(encode(&self.start));
+		bytes.push(self.moves.len().try_into().expect("too many moves"));
+		bytes.extend(self.moves.iter().map(|mv| mv.index() as u8));
+		bytes.push(match self.result {
+			GameResult::Ongoing => 0,
+			GameResult::XWins => 1,
+			GameResult::OWins => 2,
+			GameResult::Draw => 3,
+		});
+		for &score in &self.scores {
+			bytes.extend(pack_score(score).to_le_bytes());
+		}
+
+		let checksum = crc32(&bytes);
+		bytes.extend(checksum.to_le_bytes());
+		bytes
+	}
+}
+
+/// Move comments of the form `score cp 20`, as in `info` lines
+fn parse_score(comment: &str) -> Option<Score> {
+	let mut words = comment.split_whitespace();
+	let score = match (words.next()?, words.next()?, words.next()?) {
+		("score", "cp", n) => Score::Cp(n.parse().ok()?),
+		("score", "win", n) => Score::Win(n.parse().ok()?),
+		("score", "loss", n) => Score::Loss(n.parse().ok()?),
+		_ => return None,
+	};
+	words.next().is_none().then_some(score)
+}
+
+/// Keeps the scores found in move comments written as `score cp 20`, and drops everything else
+/// that a [`PackedGame`] can't hold
+impl From<&Game> for PackedGame {
+	fn from(game: &Game) -> Self {
+		let scores: Vec<_> = game
+			.moves
+			.iter()
+			.map(|mv| mv.comment.as_deref().and_then(parse_score))
+			.collect();
+
+		Self {
+			start: game.start.clone(),
+			moves: game.moves.iter().map(|mv| mv.mv).collect(),
+			result: game.result,
+			scores: match scores.iter().any(Option::is_some) {
+				true => scores,
+				false => vec![],
+			},
+		}
+	}
+}
+
+/// Writes scores as move comments like `score cp 20`
+impl From<&PackedGame> for Game {
+	fn from(packed: &PackedGame) -> Self {
+		let mut game = Game::new(packed.start.clone());
+		game.result = packed.result;
+		game.moves = packed
+			.moves
+			.iter()
+			.enumerate()
+			.map(|(i, &mv)| GameMove {
+				mv,
+				annotation: None,
+				comment: packed
+					.scores
+					.get(i)
+					.copied()
+					.flatten()
+					.map(|score| format!("score {score}")),
+			})
+			.collect();
+		game
+	}
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+	/// Reading stops after an i/o error, which includes a record cut short by the end of the input
+	Io(io::Error),
+	/// The input isn't a record, reading stops as the records that follow can't be found
+	Magic,
+	/// A format version or flags this code doesn't know, reading stops
+	Unsupported {
+		version: u8,
+		flags: u8,
+	},
+	/// The record doesn't match its checksum
+	Checksum,
+	Position(DecodeError),
+	/// The move at `ply`, counting from 0, isn't a square or isn't legal there
+	InvalidMove {
+		ply: usize,
+	},
+	InvalidResult {
+		found: u8,
+	},
+}
+
+impl fmt::Display for ReadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ReadError::Io(err) => write!(f, "i/o error: {err}"),
+			ReadError::Magic => write!(f, "not a packed game"),
+			ReadError::Unsupported { version, flags } => {
+				write!(f, "unsupported version {version} with flags {flags:#04x}")
+			}
+			ReadError::Checksum => write!(f, "checksum mismatch"),
+			ReadError::Position(err) => write!(f, "invalid start position: {err}"),
+			ReadError::InvalidMove { ply } => write!(f, "invalid move at ply {ply}"),
+			ReadError::InvalidResult { found } => write!(f, "invalid result {found}"),
+		}
+	}
+}
+
+impl Error for ReadError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			ReadError::Io(err) => Some(err),
+			ReadError::Position(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for ReadError {
+	fn from(err: io::Error) -> Self {
+		ReadError::Io(err)
+	}
+}
+
+/// Iterates over the records of a file of [`PackedGame`]s. A record that fails its checksum or
+/// holds an invalid game is reported and skipped.
+pub struct PackedReader<R> {
+	input: R,
+	done: bool,
+}
+
+impl PackedReader<BufReader<File>> {
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		Ok(Self::new(BufReader::new(File::open(path)?)))
+	}
+}
+
+/// Appends `len` bytes of `input` to `record`
+fn read_into(input: &mut impl Read, record: &mut Vec<u8>, len: usize) -> io::Result<()> {
+	let start = record.len();
+	record.resize(start + len, 0);
+	input.read_exact(&mut record[start..])
+}
+
+impl<R: BufRead> PackedReader<R> {
+	pub fn new(input: R) -> Self {
+		Self { input, done: false }
+	}
+
+	/// `None` at the end of the input
+	fn read_game(&mut self) -> Result<Option<PackedGame>, ReadError> {
+		if self.input.fill_buf()?.is_empty() {
+			return Ok(None);
+		}
+
+		let mut record = vec![];
+		read_into(&mut self.input, &mut record, 4)?;
+		if record[..2] != MAGIC {
+			return Err(ReadError::Magic);
+		}
+		let (version, flags) = (record[2], record[3]);
+		if version != VERSION || flags & !HAS_SCORES != 0 {
+			return Err(ReadError::Unsupported { version, flags });
+		}
+
+		read_into(&mut self.input, &mut record, SIZE + 1)?;
+		let count = record[4 + SIZE] as usize;
+		let score_len = if flags & HAS_SCORES != 0 {
+			2 * count
+		} else {
+			0
+		};
+		read_into(&mut self.input, &mut record, count + 1 + score_len)?;
+
+		let mut checksum = [0; 4];
+		self.input.read_exact(&mut checksum)?;
+		if u32::from_le_bytes(checksum) != crc32(&record) {
+			return Err(ReadError::Checksum);
+		}
+
+		let start = decode(&record[4..4 + SIZE]).map_err(ReadError::Position)?;
+		let (moves, rest) = record[5 + SIZE..].split_at(count);
+
+		let mut state = start.clone();
+		let moves = moves
+			.iter()
+			.enumerate()
+			.map(|(ply, &index)| {
+				Move::from_index(index as usize)
+					.filter(|&mv| state.play(mv).is_ok())
+					.ok_or(ReadError::InvalidMove { ply })
+			})
+			.collect::<Result<_, _>>()?;
+
+		let result = match rest[0] {
+			0 => GameResult::Ongoing,
+			1 => GameResult::XWins,
+			2 => GameResult::OWins,
+			3 => GameResult::Draw,
+			found => return Err(ReadError::InvalidResult { found }),
+		};
+		let scores = rest[1..]
+			.chunks_exact(2)
+			.map(|n| unpack_score(i16::from_le_bytes([n[0], n[1]])))
+			.collect();
+
+		Ok(Some(PackedGame {
+			start,
+			moves,
+			result,
+			scores,
+		}))
+	}
+}
+
+impl<R: BufRead> Iterator for PackedReader<R> {
+	type Item = Result<PackedGame, ReadError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+
+		let res = self.read_game();
+		if matches!(
+			res,
+			Ok(None) | Err(ReadError::Io(_) | ReadError::Magic | ReadError::Unsupported { .. })
+		) {
+			self.done = true;
+		}
+		res.transpose()
+	}
+}
+
+/// Writes [`PackedGame`]s one after the other
+pub struct PackedWriter<W: Write> {
+	output: W,
+}
+
+impl PackedWriter<BufWriter<File>> {
+	/// Opens `path` for appending, creating it if needed
+	pub fn append_to(path: impl AsRef<Path>) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(Self::new(BufWriter::new(file)))
+	}
+}
+
+impl<W: Write> PackedWriter<W> {
+	pub fn new(output: W) -> Self {
+		Self { output }
+	}
+
+	pub fn write(&mut self, game: &PackedGame) -> io::Result<()> {
+		self.output.write_all(&game.to_bytes())
+	}
+
+	pub fn flush(&mut self) -> io::Result<()> {
+		self.output.flush()
+	}
+
+	pub fn into_inner(self) -> W {
+		self.output
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::Lcg;
+
+	#[test]
+	fn round_trips() {
+		let mut rng = Lcg::new(11);
+		for _ in 0..20 {
+			for state in rng.positions(&State::default()) {
+				assert_eq!(decode(&encode(&state)), Ok(state.clone()));
+				let text = to_base64(&state);
+				assert_eq!(text.len(), BASE64_SIZE);
+				assert_eq!(from_base64(&text), Ok(state));
+			}
+		}
+
+		// Any squares, not just reachable ones
+		let state: State = "4/9O/9X/9O/9X/9O/9X/9O/9X/9O/i9".parse().unwrap();
+		assert_eq!(decode(&encode(&state)), Ok(state));
+	}
+
+	#[test]
+	fn encodes() {
+		assert_eq!(
+			encode(&State::default()),
+			[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 27, 0]
+		);
+		assert_eq!(to_base64(&State::default()), "AAAAAAAAAAAAAAAAAAAAABsA");
+
+		let state: State = "4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5".parse().unwrap();
+		let bytes = encode(&state);
+		// e5 is square 40, the lowest digit of the 9th byte
+		assert_eq!(bytes[8], 1);
+		assert_eq!(
+			u16::from_le_bytes([bytes[16], bytes[17]]),
+			3 * (4 + 10 * 41)
+		);
+	}
+
+	#[test]
+	fn rejects_bad_input() {
+		let bytes = encode(&State::default());
+
+		assert_eq!(decode(&bytes[1..]), Err(DecodeError::Length { found: 17 }));
+		let mut bad = bytes;
+		bad[3] = 243;
+		assert_eq!(decode(&bad), Err(DecodeError::InvalidByte { index: 3 }));
+		let mut bad = bytes;
+		bad[16..].copy_from_slice(&2460u16.to_le_bytes());
+		assert_eq!(decode(&bad), Err(DecodeError::InvalidByte { index: 17 }));
+
+		let mut text = to_base64(&State::default());
+		assert_eq!(
+			from_base64(&text[1..]),
+			Err(DecodeError::Length { found: 23 })
+		);
+		text.replace_range(5..6, "+");
+		assert_eq!(
+			from_base64(&text),
+			Err(DecodeError::InvalidChar { index: 5 })
+		);
+	}
+
+	fn random_game(rng: &mut Lcg, scored: bool) -> PackedGame {
+		let mut game = PackedGame::new(State::default());
+		game.moves = rng.game(&game.start);
+		if scored {
+			game.scores = game
+				.moves
+				.iter()
+				.map(|mv| Some(Score::Cp(rng.next(500) as i32 - 250)).filter(|_| mv.cell() != 0))
+				.collect();
+		}
+		game.result = Game::from(&game).replay().unwrap().result();
+		game
+	}
+
+	#[test]
+	fn checksums_and_scores() {
+		assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+		assert_eq!(crc32(b""), 0);
+
+		for score in [
+			None,
+			Some(Score::Cp(-20)),
+			Some(Score::Cp(31999)),
+			Some(Score::Win(0)),
+			Some(Score::Win(3)),
+			Some(Score::Loss(0)),
+			Some(Score::Loss(767)),
+		] {
+			assert_eq!(unpack_score(pack_score(score)), score);
+		}
+		assert_eq!(
+			unpack_score(pack_score(Some(Score::Cp(-100_000)))),
+			Some(Score::Cp(-31999))
+		);
+		assert_eq!(
+			unpack_score(pack_score(Some(Score::Win(1000)))),
+			Some(Score::Win(767))
+		);
+	}
+
+	#[test]
+	fn games_round_trip() {
+		let mut rng = Lcg::new(3);
+		let games: Vec<_> = (0..10).map(|i| random_game(&mut rng, i % 2 == 0)).collect();
+
+		let mut writer = PackedWriter::new(vec![]);
+		for game in &games {
+			let bytes = game.to_bytes();
+			let per_move = if game.scores.is_empty() { 1 } else { 3 };
+			assert_eq!(bytes.len(), 28 + per_move * game.moves.len());
+			writer.write(game).unwrap();
+		}
+		let bytes = writer.into_inner();
+
+		let read: Vec<_> = PackedReader::new(&bytes[..])
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(read, games);
+	}
+
+	#[test]
+	fn converts_text_records() {
+		let text = "[X \"A\"]\n\n1. e5 {score cp 20} e1 {score win 3} 2. a5 {a comment} *";
+		let packed = PackedGame::from(&crate::game::parse(text).unwrap());
+		assert_eq!(packed.moves.len(), 3);
+		assert_eq!(
+			packed.scores,
+			[Some(Score::Cp(20)), Some(Score::Win(3)), None]
+		);
+
+		let game = Game::from(&packed);
+		assert_eq!(
+			game.to_string(),
+			"[Result \"*\"]\n\n1. e5 {score cp 20} e1 {score win 3} 2. a5 *\n"
+		);
+		assert_eq!(PackedGame::from(&game), packed);
+
+		let unscored = PackedGame::from(&crate::game::parse("1. e5 e1 *").unwrap());
+		assert!(unscored.scores.is_empty());
+	}
+
+	#[test]
+	fn rejects_corrupt_records() {
+		let mut rng = Lcg::new(5);
+		let (first, second) = (random_game(&mut rng, true), random_game(&mut rng, false));
+		let mut bytes = first.to_bytes();
+		let len = bytes.len();
+		bytes.extend(second.to_bytes());
+
+		let read = |bytes: &[u8]| PackedReader::new(bytes).collect::<Vec<_>>();
+
+		// A bad record is skipped
+		let mut bad = bytes.clone();
+		bad[len - 10] ^= 1;
+		let results = read(&bad);
+		assert!(matches!(results[0], Err(ReadError::Checksum)));
+		assert_eq!(results[1].as_ref().unwrap(), &second);
+
+		let mut bad = bytes.clone();
+		bad[23] = bad[24];
+		let checksum = crc32(&bad[..len - 4]);
+		bad[len - 4..len].copy_from_slice(&checksum.to_le_bytes());
+		let results = read(&bad);
+		assert!(matches!(results[0], Err(ReadError::InvalidMove { ply: 1 })));
+		assert_eq!(results.len(), 2);
+
+		// Reading stops where records can't be found
+		let results = read(&bytes[..len + 10]);
+		assert!(matches!(results[1], Err(ReadError::Io(_))));
+		assert_eq!(results.len(), 2);
+		let results = read(&bytes[1..]);
+		assert!(matches!(results[..], [Err(ReadError::Magic)]));
+		let mut bad = bytes.clone();
+		bad[len + 2] = 2;
+		let results = read(&bad);
+		assert!(matches!(
+			results[1],
+			Err(ReadError::Unsupported {
+				version: 2,
+				flags: 0
+			})
+		));
+		assert_eq!(results.len(), 2);
+	}
+}
@@ -0,0 +1,155 @@
+//! Perft, counting the leaf nodes of the move tree to a fixed depth, for validating move generators
+//! [`REFERENCE`] holds node counts for a handful of positions that any correct generator must
+//! reproduce, [`divide`] breaks a count down by root move to narrow down where two generators
+//! disagree.
+
+use crate::{
+	bitboard::BitState,
+	state::{Move, State},
+};
+
+/// Known node counts for a position, `nodes[d - 1]` is the count at depth `d`
+#[derive(Debug, Clone, Copy)]
+pub struct Reference {
+	pub name: &'static str,
+	pub position: &'static str,
+	pub nodes: &'static [u64],
+}
+
+pub const REFERENCE: &[Reference] = &[
+	Reference {
+		name: "empty board",
+		position: "9/9_/9_/9_/9_/9_/9_/9_/9_/9_",
+		nodes: &[81, 720, 6336, 55080, 473256, 4020960, 33782544, 281067408],
+	},
+	Reference {
+		name: "sent to the centre board",
+		position: "4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5",
+		nodes: &[8, 72, 624, 5376, 45696, 383936, 3188976],
+	},
+	Reference {
+		name: "free move, sent to a won board with a drawn board on the meta-board",
+		position: "9/2_X3_2OX/4_2X2_X/6_3O/O_O_O4_/2_O2_XOXO/_OX_X_OXO/X2_X2_X2_/XO2X3O2X/2_3X2OXO/h3",
+		nodes: &[26, 238, 2014, 17052, 128435, 957747, 6402254],
+	},
+	Reference {
+		name: "game can be won within the tree",
+		position: "2/XO_OX_2OX/XO_OX2_2X/O2_X_X3_/OX2_2XO_X/2O2_OXOXO/_X3_X3O/2X_OX3_X/_2OX2OX_X/X3OXOXO_/h3",
+		nodes: &[6, 30, 185, 712, 3462, 7569, 25385],
+	},
+	Reference {
+		name: "late middlegame with most boards closed",
+		position: "6/OX_O2_2XO/X2O2X2O_X/2X_O3_2X/X2_X_O2XO/OX2_O3_X/_OXO2XOX_/_O3_X_OX/_X_3O3_/4O_X_OX/a7",
+		nodes: &[5, 29, 179, 1070, 6226, 33338, 164420],
+	},
+];
+
+/// Number of leaf nodes `depth` moves from `state`, positions where the game is decided before
+/// `depth` is reached are not counted
+pub fn perft(state: &State, depth: u32) -> u64 {
+	fn go(state: &mut State, depth: u32) -> u64 {
+		if depth == 0 {
+			return 1;
+		}
+
+		let moves: Vec<_> = state.legal_moves().collect();
+		if depth == 1 {
+			return moves.len() as u64;
+		}
+
+		moves
+			.into_iter()
+			.map(|mv| {
+				let undo = state.play(mv).unwrap();
+				let nodes = go(state, depth - 1);
+				state.undo(undo);
+				nodes
+			})
+			.sum()
+	}
+
+	go(&mut state.clone(), depth)
+}
+
+/// [`perft`] on a [`BitState`]
+pub fn perft_bits(bits: &BitState, depth: u32) -> u64 {
+	fn go(bits: &mut BitState, depth: u32) -> u64 {
+		if depth == 0 {
+			return 1;
+		}
+
+		if depth == 1 {
+			return bits.legal_mask().count_ones() as u64;
+		}
+
+		bits.legal_moves()
+			.map(|mv| {
+				let undo = bits.play(mv).unwrap();
+				let nodes = go(bits, depth - 1);
+				bits.undo(undo);
+				nodes
+			})
+			.sum()
+	}
+
+	go(&mut bits.clone(), depth)
+}
+
+/// [`perft`] for each legal move in `state`, counting `depth - 1` moves after it
+pub fn divide(state: &State, depth: u32) -> Vec<(Move, u64)> {
+	let mut state = state.clone();
+
+	state
+		.legal_moves()
+		.collect::<Vec<_>>()
+		.into_iter()
+		.map(|mv| {
+			let undo = state.play(mv).unwrap();
+			let nodes = perft(&state, depth.saturating_sub(1));
+			state.undo(undo);
+			(mv, nodes)
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::parse;
+
+	#[test]
+	fn reference_counts() {
+		for reference in REFERENCE {
+			let state = parse(reference.position).unwrap();
+			let bits = BitState::from(&state);
+			assert!(state.validate().is_empty(), "{}", reference.name);
+
+			for (depth, &nodes) in (1..).zip(reference.nodes) {
+				if nodes <= 100_000 {
+					assert_eq!(perft(&state, depth), nodes, "{} at {depth}", reference.name);
+				}
+				if nodes <= 1_000_000 {
+					assert_eq!(
+						perft_bits(&bits, depth),
+						nodes,
+						"{} at {depth}",
+						reference.name
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn divide_sums_to_perft() {
+		let state = parse(REFERENCE[2].position).unwrap();
+		let split = divide(&state, 3);
+
+		assert_eq!(split.len(), state.legal_moves().count());
+		assert_eq!(
+			split.iter().map(|(_, n)| n).sum::<u64>(),
+			REFERENCE[2].nodes[2]
+		);
+		assert!(split.iter().all(|&(mv, _)| state.is_legal(mv)));
+	}
+}
@@ -0,0 +1,291 @@
+//! GUI side of the [`protocol`](crate::protocol), for refereeing engines that run as child
+//! processes.
+//!
+//! [`EngineProcess`] owns the child, performs the handshake and asks for moves under a time limit.
+//! Engine output is read on a separate thread so that every wait has a deadline: a `go` that
+//! overruns its limit is sent `stop`, and an engine that still doesn't answer within
+//! [`EngineProcess::grace`] is considered hung. Anything that goes wrong is reported as an
+//! [`EngineError`], after which the engine should be dropped, which kills it.
+
+use std::{
+	error::Error,
+	fmt,
+	io::{self, BufRead, BufReader, Write},
+	process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+	sync::mpsc::{self, Receiver, RecvTimeoutError},
+	thread,
+	time::{Duration, Instant},
+};
+
+use crate::{
+	protocol::{EngineCommand, EngineOption, GoParams, GuiCommand, IdField, Info},
+	state::{GameResult, Move, ParseError, State},
+};
+
+#[derive(Debug)]
+pub enum EngineError {
+	/// Spawning the engine or writing to it failed
+	Io(io::Error),
+	/// The engine closed its output, `status` is its exit status if it has exited
+	Crashed { status: Option<ExitStatus> },
+	/// The engine didn't send `expected` in time
+	Timeout { expected: &'static str },
+	/// The engine sent a malformed command, lines that aren't commands at all are skipped
+	Protocol { line: String, err: ParseError },
+	/// The engine's `bestmove` isn't legal in the position, `None` when it sent `bestmove none`
+	/// while it had legal moves
+	IllegalMove { mv: Option<Move> },
+}
+
+impl fmt::Display for EngineError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			EngineError::Io(err) => write!(f, "i/o error: {err}"),
+			EngineError::Crashed {
+				status: Some(status),
+			} => write!(f, "engine crashed ({status})"),
+			EngineError::Crashed { status: None } => write!(f, "engine closed its output"),
+			EngineError::Timeout { expected } => write!(f, "engine didn't send {expected} in time"),
+			EngineError::Protocol { line, err } => write!(f, "invalid line {line:?}: {err}"),
+			EngineError::IllegalMove { mv: Some(mv) } => write!(f, "illegal move {mv}"),
+			EngineError::IllegalMove { mv: None } => write!(f, "no move played"),
+		}
+	}
+}
+
+impl Error for EngineError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			EngineError::Io(err) => Some(err),
+			EngineError::Protocol { err, .. } => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for EngineError {
+	fn from(err: io::Error) -> Self {
+		EngineError::Io(err)
+	}
+}
+
+/// The answer to [`EngineProcess::go`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+	pub best_move: Move,
+	/// Every `info` sent during the search, in order
+	pub info: Vec<Info>,
+	/// Time from sending `go` to receiving `bestmove`
+	pub elapsed: Duration,
+	/// Whether the engine overran its limit and had to be sent `stop`
+	pub stopped: bool,
+}
+
+pub struct EngineProcess {
+	child: Child,
+	stdin: ChildStdin,
+	lines: Receiver<io::Result<String>>,
+	pub name: String,
+	pub author: String,
+	pub options: Vec<EngineOption>,
+	/// How long to wait for answers to `utp`, `isready` and `stop`, one second by default
+	pub grace: Duration,
+}
+
+impl EngineProcess {
+	/// Spawns `command` with piped stdin and stdout, and performs the handshake, which must
+	/// finish within `timeout`
+	pub fn spawn(mut command: Command, timeout: Duration) -> Result<Self, EngineError> {
+		let mut child = command
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()?;
+
+		let stdin = child.stdin.take().unwrap();
+		let stdout = BufReader::new(child.stdout.take().unwrap());
+
+		let (send, lines) = mpsc::channel();
+		thread::spawn(move || {
+			for line in stdout.lines() {
+				if send.send(line).is_err() {
+					break;
+				}
+			}
+		});
+
+		let mut engine = Self {
+			child,
+			stdin,
+			lines,
+			name: String::new(),
+			author: String::new(),
+			options: vec![],
+			grace: Duration::from_secs(1),
+		};
+
+		engine.send(&GuiCommand::Utp)?;
+		let deadline = Instant::now() + timeout;
+		loop {
+			match engine.recv(deadline, "utpok")? {
+				EngineCommand::Id {
+					field: IdField::Name,
+					value,
+				} => engine.name = value,
+				EngineCommand::Id {
+					field: IdField::Author,
+					value,
+				} => engine.author = value,
+				EngineCommand::Option(option) => engine.options.push(option),
+				EngineCommand::UtpOk => break,
+				_ => {}
+			}
+		}
+
+		Ok(engine)
+	}
+
+	pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), EngineError> {
+		self.send(&GuiCommand::SetOption {
+			name: name.to_string(),
+			value: value.map(str::to_string),
+		})
+	}
+
+	/// Sends `newgame` and waits for the engine to be ready
+	pub fn new_game(&mut self) -> Result<(), EngineError> {
+		self.send(&GuiCommand::NewGame)?;
+		self.is_ready()
+	}
+
+	/// Sends `isready` and waits for `readyok`
+	pub fn is_ready(&mut self) -> Result<(), EngineError> {
+		self.send(&GuiCommand::IsReady)?;
+		let deadline = Instant::now() + self.grace;
+		while self.recv(deadline, "readyok")? != EngineCommand::ReadyOk {}
+		Ok(())
+	}
+
+	/// Asks for a move in `state`, which must be ongoing. The engine is sent `stop` if it hasn't
+	/// answered within `limit`, and must then answer within [`grace`](Self::grace).
+	pub fn go(
+		&mut self,
+		state: &State,
+		params: &GoParams,
+		limit: Duration,
+	) -> Result<Reply, EngineError> {
+		debug_assert_eq!(state.result(), GameResult::Ongoing);
+
+		self.send(&GuiCommand::Position {
+			state: state.clone(),
+			moves: vec![],
+		})?;
+		self.send(&GuiCommand::Go(params.clone()))?;
+
+		let start = Instant::now();
+		let mut deadline = start + limit;
+		let mut stopped = false;
+		let mut info = vec![];
+
+		loop {
+			let cmd = match self.recv(deadline, "bestmove") {
+				Err(EngineError::Timeout { .. }) if !stopped => {
+					self.send(&GuiCommand::Stop)?;
+					stopped = true;
+					deadline = Instant::now() + self.grace;
+					continue;
+				}
+				res => res?,
+			};
+
+			match cmd {
+				EngineCommand::Info(line) => info.push(line),
+				EngineCommand::BestMove(Some(mv)) if state.is_legal(mv) => {
+					return Ok(Reply {
+						best_move: mv,
+						info,
+						elapsed: start.elapsed(),
+						stopped,
+					});
+				}
+				EngineCommand::BestMove(mv) => return Err(EngineError::IllegalMove { mv }),
+				_ => {}
+			}
+		}
+	}
+
+	/// Sends `quit` and waits up to [`grace`](Self::grace) for the engine to exit, killing it
+	/// otherwise. `None` means it had to be killed.
+	pub fn quit(mut self) -> Result<Option<ExitStatus>, EngineError> {
+		// The engine may exit before reading `quit`
+		let _ = self.send(&GuiCommand::Quit);
+
+		let deadline = Instant::now() + self.grace;
+		while Instant::now() < deadline {
+			if let Some(status) = self.child.try_wait()? {
+				return Ok(Some(status));
+			}
+			thread::sleep(Duration::from_millis(5));
+		}
+
+		Ok(None)
+	}
+
+	fn send(&mut self, cmd: &GuiCommand) -> Result<(), EngineError> {
+		let res = writeln!(self.stdin, "{cmd}").and_then(|_| self.stdin.flush());
+
+		match res {
+			Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Err(self.crashed()),
+			res => Ok(res?),
+		}
+	}
+
+	/// Receives the next command, failing with a timeout naming `expected` after `deadline`.
+	/// Lines that don't start with a command are skipped, as UCI GUIs do.
+	fn recv(
+		&mut self,
+		deadline: Instant,
+		expected: &'static str,
+	) -> Result<EngineCommand, EngineError> {
+		const COMMANDS: [&str; 6] = ["id", "option", "utpok", "readyok", "info", "bestmove"];
+
+		loop {
+			let timeout = deadline.saturating_duration_since(Instant::now());
+
+			return match self.lines.recv_timeout(timeout) {
+				Ok(Ok(line)) => match line.parse() {
+					Ok(cmd) => Ok(cmd),
+					Err(_) if !COMMANDS.contains(&line.split_whitespace().next().unwrap_or("")) => {
+						continue;
+					}
+					Err(err) => Err(EngineError::Protocol { line, err }),
+				},
+				Ok(Err(err)) => Err(err.into()),
+				Err(RecvTimeoutError::Timeout) => Err(EngineError::Timeout { expected }),
+				Err(RecvTimeoutError::Disconnected) => Err(self.crashed()),
+			};
+		}
+	}
+
+	fn crashed(&mut self) -> EngineError {
+		// Give the engine a moment to actually exit after closing its output
+		let deadline = Instant::now() + self.grace;
+		let status = loop {
+			match self.child.try_wait() {
+				Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+				Ok(status) => break status,
+				Err(_) => break None,
+			}
+		};
+
+		EngineError::Crashed { status }
+	}
+}
+
+impl Drop for EngineProcess {
+	fn drop(&mut self) {
+		if let Ok(None) = self.child.try_wait() {
+			let _ = self.child.kill();
+		}
+		let _ = self.child.wait();
+	}
+}
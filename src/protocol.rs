@@ -0,0 +1,699 @@
+//! UTP, a line based protocol between a GUI (or referee) and an engine, modelled on UCI
+//! Every command is a single line. The GUI sends [`GuiCommand`]s:
+//! - `utp` to start the handshake, answered by `id`s, `option`s and `utpok`
+//! - `isready`, answered by `readyok` once the engine is done with earlier commands
+//! - `setoption name <name> [value <value>]`
+//! - `newgame`
+//! - `position (startpos | <utt string>) [moves <move>...]`, moves are in `e5` notation
+//! - `go [depth <plies>] [nodes <n>] [movetime <ms>] [xtime <ms>] [otime <ms>] [xinc <ms>]
+//!   [oinc <ms>] [infinite]`
+//! - `stop`, after which the engine must send `bestmove` as soon as possible
+//! - `quit`
+//!
+//! and the engine answers with [`EngineCommand`]s:
+//! - `id (name | author) <value>`
+//! - `option name <name> type (check | spin | string | button) [default <value>] [min <n>]
+//!   [max <n>]`
+//! - `utpok`
+//! - `readyok`
+//! - `info [depth <plies>] [score (cp <n> | win <plies> | loss <plies>)] [nodes <n>] [time <ms>]
+//!   [pv <move>...] [string <text>]`, where words that aren't part of these fields are skipped,
+//!   so that UCI fields such as `nps <n>` or `currmove <move>` don't make the line invalid
+//! - `bestmove (<move> | none)`
+//!
+//! Names, values and `string` text run to the next keyword or the end of the line. Both command
+//! types parse with [`FromStr`] and serialize with [`Display`](fmt::Display), and parsing what was
+//! serialized gives back the same command.
+
+use std::{fmt, str::FromStr};
+
+use chumsky::prelude::*;
+
+use crate::state::{Move, MoveErr, ParseError, State, state_parser};
+
+/// Parameters of a `go` command, `None` means no limit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoParams {
+	pub depth: Option<u32>,
+	pub nodes: Option<u64>,
+	/// Exact time to spend on this move, in milliseconds
+	pub movetime: Option<u64>,
+	/// Time left on X's clock, in milliseconds
+	pub xtime: Option<u64>,
+	/// Time left on O's clock, in milliseconds
+	pub otime: Option<u64>,
+	/// X's increment per move, in milliseconds
+	pub xinc: Option<u64>,
+	/// O's increment per move, in milliseconds
+	pub oinc: Option<u64>,
+	/// Search until told to `stop`
+	pub infinite: bool,
+}
+
+/// A command sent from the GUI to the engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuiCommand {
+	Utp,
+	IsReady,
+	SetOption {
+		name: String,
+		value: Option<String>,
+	},
+	NewGame,
+	/// The position to search is `state` after playing `moves`
+	Position {
+		state: State,
+		moves: Vec<Move>,
+	},
+	Go(GoParams),
+	Stop,
+	Quit,
+}
+
+impl GuiCommand {
+	/// The position described by a `position` command, `None` for any other command
+	pub fn position(&self) -> Option<Result<State, MoveErr>> {
+		let GuiCommand::Position { state, moves } = self else {
+			return None;
+		};
+
+		let mut state = state.clone();
+		for &mv in moves {
+			if let Err(err) = state.play(mv) {
+				return Some(Err(err));
+			}
+		}
+
+		Some(Ok(state))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdField {
+	Name,
+	Author,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionKind {
+	Check { default: bool },
+	Spin { default: i64, min: i64, max: i64 },
+	String { default: String },
+	Button,
+}
+
+/// An option the engine supports, set with `setoption`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineOption {
+	pub name: String,
+	pub kind: OptionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+	/// Heuristic score from the engine's point of view, in centi-boards or whatever the engine
+	/// likes, positive is good for the engine
+	Cp(i32),
+	/// The engine wins in this many plies
+	Win(u32),
+	/// The engine loses in this many plies
+	Loss(u32),
+}
+
+/// Search progress, every field is optional
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+	pub depth: Option<u32>,
+	pub score: Option<Score>,
+	pub nodes: Option<u64>,
+	/// Time searched so far, in milliseconds
+	pub time: Option<u64>,
+	pub pv: Vec<Move>,
+	/// Free form text, always written last
+	pub string: Option<String>,
+}
+
+/// A command sent from the engine to the GUI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineCommand {
+	Id {
+		field: IdField,
+		value: String,
+	},
+	Option(EngineOption),
+	UtpOk,
+	ReadyOk,
+	Info(Info),
+	/// `None` when there is no legal move
+	BestMove(Option<Move>),
+}
+
+type Extra = extra::Err<ParseError>;
+
+fn ws<'a>() -> impl Parser<'a, &'a str, (), Extra> + Clone {
+	text::inline_whitespace().at_least(1)
+}
+
+fn word<'a>() -> impl Parser<'a, &'a str, &'a str, Extra> + Clone {
+	any()
+		.filter(|c: &char| !c.is_whitespace())
+		.repeated()
+		.at_least(1)
+		.to_slice()
+}
+
+/// Everything up to the end of the line
+fn rest<'a>() -> impl Parser<'a, &'a str, String, Extra> + Clone {
+	any()
+		.repeated()
+		.to_slice()
+		.map(|s: &str| s.trim_end().to_string())
+}
+
+/// Words up to one of `stop` or the end of the line, joined by single spaces
+fn phrase<'a>(stop: &'static [&'static str]) -> impl Parser<'a, &'a str, String, Extra> + Clone {
+	word()
+		.filter(move |w: &&str| !stop.contains(w))
+		.separated_by(ws())
+		.at_least(1)
+		.collect::<Vec<_>>()
+		.map(|words| words.join(" "))
+}
+
+pub(crate) fn number<'a, T: FromStr>() -> impl Parser<'a, &'a str, T, Extra> + Clone {
+	just('-')
+		.or_not()
+		.then(text::int(10))
+		.to_slice()
+		.try_map(|s: &str, span: SimpleSpan| {
+			s.parse().map_err(|_| ParseError::Unexpected {
+				found: s.chars().next(),
+				span: span.into_range(),
+			})
+		})
+}
+
+fn mv<'a>() -> impl Parser<'a, &'a str, Move, Extra> + Clone {
+	word().try_map(|s: &str, span: SimpleSpan| {
+		s.parse().map_err(|err| ParseError::InvalidMove {
+			err,
+			span: span.into_range(),
+		})
+	})
+}
+
+/// A keyword followed by whitespace and a value
+pub(crate) fn param<'a, T>(
+	name: &'static str,
+	value: impl Parser<'a, &'a str, T, Extra> + Clone,
+) -> impl Parser<'a, &'a str, T, Extra> + Clone {
+	text::keyword(name).ignore_then(ws()).ignore_then(value)
+}
+
+/// Finishes a command grammar, allowing trailing whitespace
+fn line<'a, T>(parser: impl Parser<'a, &'a str, T, Extra>) -> impl Parser<'a, &'a str, T, Extra> {
+	text::inline_whitespace()
+		.ignore_then(parser)
+		.then_ignore(text::inline_whitespace())
+		.then_ignore(end().map_err(|e: ParseError| ParseError::TrailingInput { span: e.span() }))
+}
+
+fn gui_command<'a>() -> impl Parser<'a, &'a str, GuiCommand, Extra> {
+	let set_option = text::keyword("setoption")
+		.ignore_then(ws())
+		.ignore_then(param("name", phrase(&["value"])))
+		.then(ws().ignore_then(param("value", rest())).or_not())
+		.map(|(name, value)| GuiCommand::SetOption { name, value });
+
+	let position = text::keyword("position")
+		.ignore_then(ws())
+		.ignore_then(choice((
+			text::keyword("startpos").to(State::default()),
+			state_parser(),
+		)))
+		.then(
+			ws().ignore_then(text::keyword("moves"))
+				.ignore_then(ws().ignore_then(mv()).repeated().collect())
+				.or_not(),
+		)
+		.map(|(state, moves)| GuiCommand::Position {
+			state,
+			moves: moves.unwrap_or_default(),
+		});
+
+	#[derive(Clone)]
+	enum Go {
+		Depth(u32),
+		Nodes(u64),
+		Movetime(u64),
+		Xtime(u64),
+		Otime(u64),
+		Xinc(u64),
+		Oinc(u64),
+		Infinite,
+	}
+
+	let go_param = choice((
+		param("depth", number()).map(Go::Depth),
+		param("nodes", number()).map(Go::Nodes),
+		param("movetime", number()).map(Go::Movetime),
+		param("xtime", number()).map(Go::Xtime),
+		param("otime", number()).map(Go::Otime),
+		param("xinc", number()).map(Go::Xinc),
+		param("oinc", number()).map(Go::Oinc),
+		text::keyword("infinite").to(Go::Infinite),
+	));
+
+	let go = text::keyword("go")
+		.ignore_then(ws().ignore_then(go_param).repeated().collect::<Vec<_>>())
+		.map(|params| {
+			let mut go = GoParams::default();
+			for param in params {
+				match param {
+					Go::Depth(n) => go.depth = Some(n),
+					Go::Nodes(n) => go.nodes = Some(n),
+					Go::Movetime(n) => go.movetime = Some(n),
+					Go::Xtime(n) => go.xtime = Some(n),
+					Go::Otime(n) => go.otime = Some(n),
+					Go::Xinc(n) => go.xinc = Some(n),
+					Go::Oinc(n) => go.oinc = Some(n),
+					Go::Infinite => go.infinite = true,
+				}
+			}
+			GuiCommand::Go(go)
+		});
+
+	line(choice((
+		text::keyword("utp").to(GuiCommand::Utp),
+		text::keyword("isready").to(GuiCommand::IsReady),
+		set_option,
+		text::keyword("newgame").to(GuiCommand::NewGame),
+		position,
+		go,
+		text::keyword("stop").to(GuiCommand::Stop),
+		text::keyword("quit").to(GuiCommand::Quit),
+	)))
+}
+
+fn engine_command<'a>() -> impl Parser<'a, &'a str, EngineCommand, Extra> {
+	let id = text::keyword("id")
+		.ignore_then(ws())
+		.ignore_then(choice((
+			text::keyword("name").to(IdField::Name),
+			text::keyword("author").to(IdField::Author),
+		)))
+		.then_ignore(ws())
+		.then(rest())
+		.map(|(field, value)| EngineCommand::Id { field, value });
+
+	let boolean = choice((
+		text::keyword("true").to(true),
+		text::keyword("false").to(false),
+	));
+	let kind = choice((
+		text::keyword("check")
+			.ignore_then(ws())
+			.ignore_then(param("default", boolean))
+			.map(|default| OptionKind::Check { default }),
+		text::keyword("spin")
+			.ignore_then(ws())
+			.ignore_then(param("default", number()))
+			.then_ignore(ws())
+			.then(param("min", number()))
+			.then_ignore(ws())
+			.then(param("max", number()))
+			.map(|((default, min), max)| OptionKind::Spin { default, min, max }),
+		text::keyword("string")
+			.ignore_then(ws())
+			.ignore_then(text::keyword("default"))
+			.ignore_then(ws().ignore_then(rest()).or_not())
+			.map(|default| OptionKind::String {
+				default: default.unwrap_or_default(),
+			}),
+		text::keyword("button").to(OptionKind::Button),
+	));
+	let option = text::keyword("option")
+		.ignore_then(ws())
+		.ignore_then(param("name", phrase(&["type"])))
+		.then_ignore(ws())
+		.then(param("type", kind))
+		.map(|(name, kind)| EngineCommand::Option(EngineOption { name, kind }));
+
+	#[derive(Clone)]
+	enum Item {
+		Depth(u32),
+		Score(Score),
+		Nodes(u64),
+		Time(u64),
+		Pv(Vec<Move>),
+		String(String),
+	}
+
+	let score = choice((
+		param("cp", number()).map(Score::Cp),
+		param("win", number()).map(Score::Win),
+		param("loss", number()).map(Score::Loss),
+	));
+	let item = choice((
+		param("depth", number()).map(Item::Depth),
+		param("score", score).map(Item::Score),
+		param("nodes", number()).map(Item::Nodes),
+		param("time", number()).map(Item::Time),
+		param("pv", mv().separated_by(ws()).at_least(1).collect()).map(Item::Pv),
+		text::keyword("string")
+			.ignore_then(ws().ignore_then(rest()).or_not())
+			.map(|s| Item::String(s.unwrap_or_default())),
+	));
+	let info = text::keyword("info")
+		.ignore_then(
+			ws().ignore_then(item.map(Some).or(word().to(None)))
+				.repeated()
+				.collect::<Vec<_>>(),
+		)
+		.map(|items| {
+			let mut info = Info::default();
+			for item in items.into_iter().flatten() {
+				match item {
+					Item::Depth(n) => info.depth = Some(n),
+					Item::Score(score) => info.score = Some(score),
+					Item::Nodes(n) => info.nodes = Some(n),
+					Item::Time(n) => info.time = Some(n),
+					Item::Pv(pv) => info.pv = pv,
+					Item::String(s) => info.string = Some(s),
+				}
+			}
+			EngineCommand::Info(info)
+		});
+
+	let best_move = param(
+		"bestmove",
+		choice((text::keyword("none").to(None), mv().map(Some))),
+	)
+	.map(EngineCommand::BestMove);
+
+	line(choice((
+		id,
+		option,
+		text::keyword("utpok").to(EngineCommand::UtpOk),
+		text::keyword("readyok").to(EngineCommand::ReadyOk),
+		info,
+		best_move,
+	)))
+}
+
+fn first_error<T>(res: ParseResult<T, ParseError>) -> Result<T, ParseError> {
+	res.into_result()
+		.map_err(|errs| errs.into_iter().next().unwrap())
+}
+
+impl FromStr for GuiCommand {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		first_error(gui_command().parse(s))
+	}
+}
+
+impl FromStr for EngineCommand {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		first_error(engine_command().parse(s))
+	}
+}
+
+impl fmt::Display for GoParams {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let params = [
+			("depth", self.depth.map(u64::from)),
+			("nodes", self.nodes),
+			("movetime", self.movetime),
+			("xtime", self.xtime),
+			("otime", self.otime),
+			("xinc", self.xinc),
+			("oinc", self.oinc),
+		];
+
+		write!(f, "go")?;
+		for (name, value) in params {
+			if let Some(value) = value {
+				write!(f, " {name} {value}")?;
+			}
+		}
+
+		if self.infinite {
+			write!(f, " infinite")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Display for GuiCommand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			GuiCommand::Utp => write!(f, "utp"),
+			GuiCommand::IsReady => write!(f, "isready"),
+			GuiCommand::SetOption { name, value } => {
+				write!(f, "setoption name {name}")?;
+				if let Some(value) = value {
+					write!(f, " value {value}")?;
+				}
+				Ok(())
+			}
+			GuiCommand::NewGame => write!(f, "newgame"),
+			GuiCommand::Position { state, moves } => {
+				write!(f, "position {state}")?;
+				if !moves.is_empty() {
+					write!(f, " moves")?;
+					for mv in moves {
+						write!(f, " {mv}")?;
+					}
+				}
+				Ok(())
+			}
+			GuiCommand::Go(go) => write!(f, "{go}"),
+			GuiCommand::Stop => write!(f, "stop"),
+			GuiCommand::Quit => write!(f, "quit"),
+		}
+	}
+}
+
+impl fmt::Display for Score {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Score::Cp(n) => write!(f, "cp {n}"),
+			Score::Win(n) => write!(f, "win {n}"),
+			Score::Loss(n) => write!(f, "loss {n}"),
+		}
+	}
+}
+
+impl fmt::Display for Info {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "info")?;
+
+		if let Some(depth) = self.depth {
+			write!(f, " depth {depth}")?;
+		}
+		if let Some(score) = self.score {
+			write!(f, " score {score}")?;
+		}
+		if let Some(nodes) = self.nodes {
+			write!(f, " nodes {nodes}")?;
+		}
+		if let Some(time) = self.time {
+			write!(f, " time {time}")?;
+		}
+		if !self.pv.is_empty() {
+			write!(f, " pv")?;
+			for mv in &self.pv {
+				write!(f, " {mv}")?;
+			}
+		}
+		if let Some(string) = &self.string {
+			write!(f, " string")?;
+			if !string.is_empty() {
+				write!(f, " {string}")?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Display for EngineOption {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "option name {} type ", self.name)?;
+
+		match &self.kind {
+			OptionKind::Check { default } => write!(f, "check default {default}"),
+			OptionKind::Spin { default, min, max } => {
+				write!(f, "spin default {default} min {min} max {max}")
+			}
+			OptionKind::String { default } if default.is_empty() => write!(f, "string default"),
+			OptionKind::String { default } => write!(f, "string default {default}"),
+			OptionKind::Button => write!(f, "button"),
+		}
+	}
+}
+
+impl fmt::Display for EngineCommand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			EngineCommand::Id { field, value } => {
+				let field = match field {
+					IdField::Name => "name",
+					IdField::Author => "author",
+				};
+				write!(f, "id {field} {value}")
+			}
+			EngineCommand::Option(option) => write!(f, "{option}"),
+			EngineCommand::UtpOk => write!(f, "utpok"),
+			EngineCommand::ReadyOk => write!(f, "readyok"),
+			EngineCommand::Info(info) => write!(f, "{info}"),
+			EngineCommand::BestMove(Some(mv)) => write!(f, "bestmove {mv}"),
+			EngineCommand::BestMove(None) => write!(f, "bestmove none"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::parse;
+
+	fn round_trip<T>(line: &str) -> T
+	where
+		T: FromStr<Err = ParseError> + fmt::Display + PartialEq + fmt::Debug,
+	{
+		let cmd: T = line.parse().unwrap();
+		assert_eq!(cmd.to_string().parse::<T>().unwrap(), cmd, "{line}");
+		cmd
+	}
+
+	#[test]
+	fn gui_commands() {
+		for line in [
+			"utp",
+			"isready",
+			"newgame",
+			"stop",
+			"quit",
+			"setoption name Hash Size value 64 MB",
+			"setoption name Clear Hash",
+			"position 4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5",
+			"go",
+			"go infinite",
+			"go xtime 1000 otime 900 xinc 10 oinc 10",
+		] {
+			assert_eq!(round_trip::<GuiCommand>(line).to_string(), line);
+		}
+
+		assert_eq!(
+			round_trip::<GuiCommand>("setoption name Hash Size value 64 MB"),
+			GuiCommand::SetOption {
+				name: "Hash Size".into(),
+				value: Some("64 MB".into())
+			}
+		);
+
+		let cmd = round_trip::<GuiCommand>("  position startpos moves e5 e1 a5  ");
+		assert_eq!(
+			cmd.to_string(),
+			"position 9/9_/9_/9_/9_/9_/9_/9_/9_/9_ moves e5 e1 a5"
+		);
+		assert_eq!(
+			cmd.position().unwrap().unwrap(),
+			parse("4/4_X4_/9_/9_/9_/O3_X4_/9_/9_/9_/9_/a5").unwrap()
+		);
+
+		// The side to move token and a position ending in its last move both fit before `moves`
+		let cmd =
+			round_trip::<GuiCommand>("position 4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5 o moves e9");
+		assert_eq!(cmd.position().unwrap().unwrap().active, 8);
+		let cmd: GuiCommand = "position startpos moves e5 a1".parse().unwrap();
+		assert_eq!(cmd.position(), Some(Err(MoveErr::Illegal)));
+
+		assert_eq!(
+			round_trip::<GuiCommand>("go depth 5 nodes 100000 movetime 250 infinite"),
+			GuiCommand::Go(GoParams {
+				depth: Some(5),
+				nodes: Some(100_000),
+				movetime: Some(250),
+				infinite: true,
+				..GoParams::default()
+			})
+		);
+	}
+
+	#[test]
+	fn engine_commands() {
+		for line in [
+			"id name Deep Toe 2",
+			"id author Someone",
+			"utpok",
+			"readyok",
+			"bestmove e5",
+			"bestmove none",
+			"option name Hash Size type spin default 16 min 1 max 1024",
+			"option name Ponder type check default false",
+			"option name Book File type string default books/main.txt",
+			"option name Clear Hash type button",
+			"info depth 7 score cp -35 nodes 12345 time 88 pv e5 e1 a5",
+			"info score win 3 string found a win",
+			"info string",
+		] {
+			assert_eq!(round_trip::<EngineCommand>(line).to_string(), line);
+		}
+
+		assert_eq!(
+			round_trip::<EngineCommand>("option name Book type string default"),
+			EngineCommand::Option(EngineOption {
+				name: "Book".into(),
+				kind: OptionKind::String {
+					default: String::new()
+				},
+			})
+		);
+		assert_eq!(
+			"info depth 3 seldepth 5 nps 900 currmove e5 pv e5 e1 hashfull 12".parse(),
+			Ok(EngineCommand::Info(Info {
+				depth: Some(3),
+				pv: vec!["e5".parse().unwrap(), "e1".parse().unwrap()],
+				..Info::default()
+			}))
+		);
+		assert_eq!(
+			round_trip::<EngineCommand>("info pv a1 b2 nodes 5"),
+			EngineCommand::Info(Info {
+				nodes: Some(5),
+				pv: vec!["a1".parse().unwrap(), "b2".parse().unwrap()],
+				..Info::default()
+			})
+		);
+	}
+
+	#[test]
+	fn errors() {
+		assert!("utpx".parse::<GuiCommand>().is_err());
+		assert!("go depth".parse::<GuiCommand>().is_err());
+		assert!("go depth -1".parse::<GuiCommand>().is_err());
+		assert!(matches!(
+			"position 9/9_".parse::<GuiCommand>(),
+			Err(ParseError::Unexpected { .. } | ParseError::SquareCount { .. })
+		));
+		assert_eq!(
+			"position startpos moves e5 z1".parse::<GuiCommand>(),
+			Err(ParseError::InvalidMove {
+				err: MoveErr::InvalidBoard,
+				span: 27..29
+			})
+		);
+		assert_eq!(
+			"bestmove e5 e1".parse::<EngineCommand>(),
+			Err(ParseError::TrailingInput { span: 12..13 })
+		);
+		assert!("readyok now".parse::<EngineCommand>().is_err());
+	}
+}
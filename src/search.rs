@@ -0,0 +1,474 @@
+//! Alpha-beta search on [`State`]
+//! [`Searcher`] runs an iterative deepening negamax search with a transposition table keyed by
+//! [`zobrist`] hashes, ordering moves by the transposition table move, then killer moves, then the
+//! history heuristic. Positions at the horizon are scored by an [`Evaluate`] implementation, which
+//! defaults to [`Heuristic`].
+
+use std::sync::{
+	Arc,
+	atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+	state::{BoardStatus, GameResult, LINES, Move, Square, State},
+	zobrist,
+};
+
+/// Score of a won position, wins found sooner score higher
+pub const WIN: i32 = 1_000_000;
+
+/// No game is longer than this many plies
+const MAX_PLY: usize = 81;
+
+/// How often the stop flag is checked, in nodes
+const STOP_CHECK_INTERVAL: u64 = 1024;
+
+/// Static evaluation of positions at the search horizon
+pub trait Evaluate {
+	/// Score of a position where the game isn't decided, from the side to move's point of view.
+	/// Scores should stay well below [`WIN`] in magnitude.
+	fn evaluate(&self, state: &State) -> i32;
+}
+
+impl<F: Fn(&State) -> i32> Evaluate for F {
+	fn evaluate(&self, state: &State) -> i32 {
+		self(state)
+	}
+}
+
+/// Default evaluation, counting won boards and lines that are still open to only one side, on
+/// the meta-board and on every open board, with the centre and corners weighted higher
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Heuristic;
+
+impl Evaluate for Heuristic {
+	fn evaluate(&self, state: &State) -> i32 {
+		const WEIGHTS: [i32; 9] = [3, 2, 3, 2, 4, 2, 3, 2, 3];
+
+		// Score of a line from X's point of view given how many marks each side has on it
+		let line_score = |x: usize, o: usize, two: i32, one: i32| match (x, o) {
+			(2, 0) => two,
+			(1, 0) => one,
+			(0, 2) => -two,
+			(0, 1) => -one,
+			_ => 0,
+		};
+
+		let meta = state.meta_board();
+		let mut score = 0;
+
+		for (board, status) in meta.iter().enumerate() {
+			score += WEIGHTS[board]
+				* match status {
+					BoardStatus::XWon => 100,
+					BoardStatus::OWon => -100,
+					BoardStatus::Drawn => 0,
+					BoardStatus::Open => {
+						let sqs = state.board(board as u8);
+						LINES
+							.iter()
+							.map(|line| {
+								let count = |mark| line.iter().filter(|&&i| sqs[i] == mark).count();
+								line_score(count(Square::X), count(Square::O), 8, 1)
+							})
+							.sum()
+					}
+				};
+		}
+
+		for line in LINES {
+			let count = |status| line.iter().filter(|&&i| meta[i] == status).count();
+
+			// A drawn board blocks the line for both sides
+			if count(BoardStatus::Drawn) == 0 {
+				score += line_score(count(BoardStatus::XWon), count(BoardStatus::OWon), 300, 50);
+			}
+		}
+
+		match state.side_to_move() {
+			Square::O => -score,
+			_ => score,
+		}
+	}
+}
+
+/// When to stop searching, the search runs until the game is solved if none of these are set
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+	/// Deepest iteration to run, in plies
+	pub depth: Option<u32>,
+	/// Nodes to visit before stopping
+	pub nodes: Option<u64>,
+	/// Stops the search as soon as it is set
+	pub stop: Option<Arc<AtomicBool>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+	/// `None` when the game is already decided
+	pub best_move: Option<Move>,
+	/// Score of the last completed iteration from the side to move's point of view, see [`WIN`]
+	pub score: i32,
+	/// Principal variation, starting with the best move
+	pub pv: Vec<Move>,
+	/// Depth of the last completed iteration
+	pub depth: u32,
+	pub nodes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+	Exact,
+	Lower,
+	Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+	key: u64,
+	depth: u8,
+	score: i32,
+	bound: Bound,
+	mv: Option<Move>,
+}
+
+/// Win scores are relative to the root, but stored relative to the node so they stay correct when
+/// the position is reached at a different ply
+fn to_tt(score: i32, ply: usize) -> i32 {
+	if score >= WIN - MAX_PLY as i32 {
+		score + ply as i32
+	} else if score <= -WIN + MAX_PLY as i32 {
+		score - ply as i32
+	} else {
+		score
+	}
+}
+
+fn from_tt(score: i32, ply: usize) -> i32 {
+	if score >= WIN - MAX_PLY as i32 {
+		score - ply as i32
+	} else if score <= -WIN + MAX_PLY as i32 {
+		score + ply as i32
+	} else {
+		score
+	}
+}
+
+fn side_index(side: Square) -> usize {
+	match side {
+		Square::O => 1,
+		_ => 0,
+	}
+}
+
+/// Search state kept between searches, reuse one `Searcher` over a game so the transposition table
+/// and move ordering carry over
+pub struct Searcher<E> {
+	eval: E,
+	tt: Vec<Option<Entry>>,
+	killers: [[Option<Move>; 2]; MAX_PLY + 1],
+	history: [[u32; 81]; 2],
+	nodes: u64,
+	node_limit: u64,
+	stop: Option<Arc<AtomicBool>>,
+	stopped: bool,
+}
+
+impl<E: Evaluate> Searcher<E> {
+	/// `tt_size` is the number of transposition table entries, rounded up to a power of 2
+	pub fn new(eval: E, tt_size: usize) -> Self {
+		Self {
+			eval,
+			tt: vec![None; tt_size.max(1).next_power_of_two()],
+			killers: [[None; 2]; MAX_PLY + 1],
+			history: [[0; 81]; 2],
+			nodes: 0,
+			node_limit: u64::MAX,
+			stop: None,
+			stopped: false,
+		}
+	}
+
+	/// Forgets everything learned in previous searches, e.g. when a new game starts
+	pub fn clear(&mut self) {
+		self.tt.fill(None);
+		self.killers = [[None; 2]; MAX_PLY + 1];
+		self.history = [[0; 81]; 2];
+	}
+
+	pub fn search(&mut self, state: &State, limits: &Limits) -> SearchResult {
+		self.nodes = 0;
+		self.node_limit = limits.nodes.unwrap_or(u64::MAX);
+		self.stop = limits.stop.clone();
+		self.stopped = false;
+
+		let mut state = state.clone();
+		let key = zobrist::hash(&state);
+		let mut result = SearchResult {
+			best_move: state.legal_moves().next(),
+			score: 0,
+			pv: vec![],
+			depth: 0,
+			nodes: 0,
+		};
+
+		let max_depth = limits.depth.unwrap_or(MAX_PLY as u32).min(MAX_PLY as u32);
+		for depth in 1..=max_depth {
+			if self
+				.stop
+				.as_ref()
+				.is_some_and(|stop| stop.load(Ordering::Relaxed))
+			{
+				break;
+			}
+
+			let mut pv = vec![];
+			let score = self.negamax(&mut state, key, depth, 0, -WIN - 1, WIN + 1, &mut pv);
+
+			// A partial iteration can't be trusted, keep the last complete one
+			if self.stopped {
+				break;
+			}
+
+			result = SearchResult {
+				best_move: pv.first().copied().or(result.best_move),
+				score,
+				pv,
+				depth,
+				nodes: self.nodes,
+			};
+
+			// Either the game is decided or searching deeper can't change the result
+			if result.best_move.is_none() || score.abs() >= WIN - MAX_PLY as i32 {
+				break;
+			}
+		}
+
+		result.nodes = self.nodes;
+		result
+	}
+
+	fn should_stop(&mut self) -> bool {
+		if self.nodes > self.node_limit {
+			self.stopped = true;
+		} else if self.nodes.is_multiple_of(STOP_CHECK_INTERVAL)
+			&& let Some(stop) = &self.stop
+		{
+			self.stopped = stop.load(Ordering::Relaxed);
+		}
+
+		self.stopped
+	}
+
+	fn probe(&self, key: u64) -> Option<Entry> {
+		self.tt[key as usize & (self.tt.len() - 1)].filter(|entry| entry.key == key)
+	}
+
+	fn store(&mut self, entry: Entry) {
+		let len = self.tt.len();
+		self.tt[entry.key as usize & (len - 1)] = Some(entry);
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn negamax(
+		&mut self,
+		state: &mut State,
+		key: u64,
+		depth: u32,
+		ply: usize,
+		mut alpha: i32,
+		beta: i32,
+		pv: &mut Vec<Move>,
+	) -> i32 {
+		pv.clear();
+		self.nodes += 1;
+		if self.should_stop() {
+			return 0;
+		}
+
+		match state.result() {
+			GameResult::Ongoing => {}
+			GameResult::Draw => return 0,
+			// The side that just moved won
+			_ => return -(WIN - ply as i32),
+		}
+
+		if depth == 0 {
+			return self.eval.evaluate(state);
+		}
+
+		let alpha_orig = alpha;
+		let mut tt_move = None;
+		if let Some(entry) = self.probe(key) {
+			tt_move = entry.mv;
+
+			if ply > 0 && entry.depth as u32 >= depth {
+				let score = from_tt(entry.score, ply);
+				match entry.bound {
+					Bound::Exact => return score,
+					Bound::Lower if score >= beta => return score,
+					Bound::Upper if score <= alpha => return score,
+					_ => {}
+				}
+			}
+		}
+
+		let side = state.side_to_move();
+		let history = &self.history[side_index(side)];
+		let killers = self.killers[ply];
+		let mut moves: Vec<_> = state.legal_moves().collect();
+		moves.sort_by_cached_key(|&mv| {
+			std::cmp::Reverse(if Some(mv) == tt_move {
+				u32::MAX
+			} else if Some(mv) == killers[0] {
+				u32::MAX - 1
+			} else if Some(mv) == killers[1] {
+				u32::MAX - 2
+			} else {
+				history[mv.index()]
+			})
+		});
+
+		let mut best = -WIN - 1;
+		let mut best_move = None;
+		let mut child_pv = vec![];
+
+		for mv in moves {
+			let old_active = state.active;
+			let undo = state.play(mv).unwrap();
+			let child_key = zobrist::update(key, mv, side, old_active, state.active);
+			let score = -self.negamax(
+				state,
+				child_key,
+				depth - 1,
+				ply + 1,
+				-beta,
+				-alpha,
+				&mut child_pv,
+			);
+			state.undo(undo);
+
+			if self.stopped {
+				return 0;
+			}
+
+			if score > best {
+				best = score;
+				best_move = Some(mv);
+
+				if score > alpha {
+					alpha = score;
+					pv.clear();
+					pv.push(mv);
+					pv.extend_from_slice(&child_pv);
+				}
+			}
+
+			if alpha >= beta {
+				let killers = &mut self.killers[ply];
+				if killers[0] != Some(mv) {
+					killers[1] = killers[0];
+					killers[0] = Some(mv);
+				}
+
+				let history = &mut self.history[side_index(side)][mv.index()];
+				*history = history.saturating_add(depth * depth);
+				break;
+			}
+		}
+
+		let bound = if best <= alpha_orig {
+			Bound::Upper
+		} else if best >= beta {
+			Bound::Lower
+		} else {
+			Bound::Exact
+		};
+
+		self.store(Entry {
+			key,
+			depth: depth as u8,
+			score: to_tt(best, ply),
+			bound,
+			mv: best_move,
+		});
+
+		best
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::parse;
+
+	/// X to move can win the game by taking board c
+	const WINNING: &str =
+		"2/XO_OX_2OX/XO_OX2_2X/O2_X_X3_/OX2_2XO_X/2O2_OXOXO/_X3_X3O/2X_OX3_X/_2OX2OX_X/X3OXOXO_/h3";
+
+	#[test]
+	fn finds_the_win() {
+		let state = parse(WINNING).unwrap();
+		let mut searcher = Searcher::new(Heuristic, 1 << 16);
+		let result = searcher.search(&state, &Limits::default());
+
+		assert_eq!(result.score, WIN - 1);
+		assert_eq!(result.pv.len(), 1);
+
+		let mut after = state.clone();
+		after.play(result.best_move.unwrap()).unwrap();
+		assert_ne!(after.result(), GameResult::Ongoing);
+	}
+
+	#[test]
+	fn principal_variation_is_legal() {
+		let state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+		let mut searcher = Searcher::new(Heuristic, 1 << 16);
+		let limits = Limits {
+			depth: Some(4),
+			..Limits::default()
+		};
+		let result = searcher.search(&state, &limits);
+
+		assert_eq!(result.depth, 4);
+		assert_eq!(result.pv.first().copied(), result.best_move);
+		assert_eq!(result.pv.len(), 4);
+
+		let mut state = state;
+		for mv in result.pv {
+			state.play(mv).unwrap();
+		}
+	}
+
+	#[test]
+	fn stops_on_limits() {
+		let state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+		let mut searcher = Searcher::new(|_: &State| 0, 1 << 10);
+
+		let limits = Limits {
+			nodes: Some(5_000),
+			..Limits::default()
+		};
+		let result = searcher.search(&state, &limits);
+		assert!(result.nodes <= 5_001);
+		assert!(state.is_legal(result.best_move.unwrap()));
+
+		// Stopped before the first iteration finished, there's still a move to play
+		let limits = Limits {
+			stop: Some(Arc::new(AtomicBool::new(true))),
+			..Limits::default()
+		};
+		let result = searcher.search(&state, &limits);
+		assert_eq!(result.depth, 0);
+		assert!(state.is_legal(result.best_move.unwrap()));
+	}
+
+	#[test]
+	fn decided_game_has_no_move() {
+		let state = parse("9/3O6_/3O6_/3O6_/9_/9_/9_/9_/9_/9_").unwrap();
+		let result = Searcher::new(Heuristic, 16).search(&state, &Limits::default());
+
+		assert_eq!(result.best_move, None);
+		assert_eq!(result.score, -WIN);
+	}
+}
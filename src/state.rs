@@ -2,42 +2,246 @@
 //! UTT strings consist of:
 //! - a single digit denoting the active board index (0-8, with 9 meaning "any board") and a slash
 //! - 9 series of 9 X's, O's, or _'s, separated by slashes
+//!
 //! some additional features:
 //! - if there is a run of multiple of the same character (e.g. XXXX or OOOOOOO) it may be replaced by
-//! the length of the run followed by that character (e.g. 4X or 7O), runs must be 1..=9.
+//!   the length of the run followed by that character (e.g. 4X or 7O), runs must be 1..=9.
 //! - the last slash may be optionally succeeded by a move of the form [a..=i][1..=9] (e.g. a1 or g9)
-//! to denote the most recent move played
+//!   to denote the most recent move played
+//! - the string may end with a space and `x` or `o` to state the side to move, which must agree with
+//!   [`State::side_to_move`]
+//!
+//! [`State`]'s [`Display`](fmt::Display) impl writes the canonical form, where every run of 2 or more
+//! identical squares is compressed and the last move is preceded by a slash, the alternate flag
+//! (`{:#}`) writes every square out instead and [`State::with_side`] appends the side to move, with
+//! the plus flag (`{:+}`) as a shortcut for it.
+//! Parsing any of these forms gives back the same state.
 
-use chumsky::prelude::*;
-use std::ops::RangeInclusive;
+use std::{fmt, ops::Range, str::FromStr};
 
-#[derive(Debug, Clone, Copy)]
+use chumsky::{
+	error::{Error, LabelError},
+	prelude::*,
+	util::MaybeRef,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Square {
 	Empty,
 	X,
 	O,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Square {
+	fn to_char(self) -> char {
+		match self {
+			Square::Empty => '_',
+			Square::X => 'X',
+			Square::O => 'O',
+		}
+	}
+}
+
+/// Status of a single sub-board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BoardStatus {
+	Open,
+	XWon,
+	OWon,
+	/// Full without either side getting 3 in a row
+	Drawn,
+}
+
+impl BoardStatus {
+	/// Whether the board can no longer be played in
+	pub fn is_closed(self) -> bool {
+		self != BoardStatus::Open
+	}
+}
+
+/// Outcome of the whole game, decided by 3 sub-boards in a row on the meta-board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameResult {
+	Ongoing,
+	XWins,
+	OWins,
+	/// Every board is closed without either side winning 3 in a row
+	Draw,
+}
+
+/// Written as in game records: `1-0` when X wins, `0-1` when O wins, `1/2-1/2` or `*`
+impl fmt::Display for GameResult {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			GameResult::Ongoing => "*",
+			GameResult::XWins => "1-0",
+			GameResult::OWins => "0-1",
+			GameResult::Draw => "1/2-1/2",
+		})
+	}
+}
+
+/// The 8 lines of 3 cells that win a board, as cell indices
+pub(crate) const LINES: [[usize; 3]; 8] = [
+	[0, 1, 2],
+	[3, 4, 5],
+	[6, 7, 8],
+	[0, 3, 6],
+	[1, 4, 7],
+	[2, 5, 8],
+	[0, 4, 8],
+	[2, 4, 6],
+];
+
+/// The mark completing a line in `cells`, if any
+fn line_winner(cells: &[Square; 9]) -> Option<Square> {
+	LINES
+		.iter()
+		.find(|&&[a, b, c]| {
+			cells[a] != Square::Empty && cells[a] == cells[b] && cells[b] == cells[c]
+		})
+		.map(|&[a, ..]| cells[a])
+}
+
+fn has_line(cells: &[Square; 9], mark: Square) -> bool {
+	LINES
+		.iter()
+		.any(|line| line.iter().all(|&i| cells[i] == mark))
+}
+
+/// A way in which a [`State`] can't have been reached by legal play from the empty board, see
+/// [`State::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Violation {
+	/// X moves first, so it must have as many marks as O or one more
+	MarkCount { x: usize, o: usize },
+	/// Both sides have 3 in a row on the same sub-board
+	BoardWonTwice { board: u8 },
+	/// Play continued after the game was decided
+	PlayAfterGameOver,
+	/// `active` is above 9
+	ActiveOutOfRange { active: u8 },
+	/// `active` points at a board that is won or full
+	ActiveClosed { board: u8 },
+	/// `active` isn't the board the last move sent the side to move to
+	ActiveMismatch { expected: u8 },
+	/// `last_move` points at an empty square
+	LastMoveEmpty,
+	/// `last_move` points at a mark of the side to move
+	LastMoveWrongSide,
+}
+
+impl fmt::Display for Violation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Violation::MarkCount { x, o } => write!(f, "{x} X marks and {o} O marks"),
+			Violation::BoardWonTwice { board } => write!(f, "board {board} won by both sides"),
+			Violation::PlayAfterGameOver => write!(f, "play continued after the game was decided"),
+			Violation::ActiveOutOfRange { active } => write!(f, "active board {active} is above 9"),
+			Violation::ActiveClosed { board } => write!(f, "active board {board} is closed"),
+			Violation::ActiveMismatch { expected } => {
+				write!(f, "active board should be {expected} after the last move")
+			}
+			Violation::LastMoveEmpty => write!(f, "last move points at an empty square"),
+			Violation::LastMoveWrongSide => write!(f, "last move was made by the side to move"),
+		}
+	}
+}
+
+/// A square on the grid, addressed by sub-board and cell.
+///
+/// Both are numbered 0..=8 in reading order, so board 0 is the top left sub-board and cell 4 is the
+/// centre of its sub-board. This matches the order of [`State::squares`], see [`Move::index`], and
+/// the textual notation used by UTT strings, where the board is a letter a..=i and the cell a digit
+/// 1..=9 (e.g. `e5` is the centre of the centre board), see the [`Display`](fmt::Display) and
+/// [`FromStr`] impls. [`Coord`] addresses the same squares by global row and column instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Move {
+	board: u8,
+	cell: u8,
+}
+
+/// A square on the grid, addressed by row and column of the full 9x9 grid, both 0..=8 starting at
+/// the top left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Coord {
 	row: u8,
-	column: u8,
+	col: u8,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MoveErr {
+	InvalidBoard,
+	InvalidCell,
 	InvalidRow,
 	InvalidColumn,
+	InvalidNotation,
+	Illegal,
 }
 
+impl fmt::Display for MoveErr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			MoveErr::InvalidBoard => write!(f, "board must be in a..=i"),
+			MoveErr::InvalidCell => write!(f, "cell must be in 1..=9"),
+			MoveErr::InvalidRow => write!(f, "row must be in 0..=8"),
+			MoveErr::InvalidColumn => write!(f, "column must be in 0..=8"),
+			MoveErr::InvalidNotation => write!(f, "move must be of the form [a..=i][1..=9]"),
+			MoveErr::Illegal => write!(f, "move is not legal in this position"),
+		}
+	}
+}
+
+impl std::error::Error for MoveErr {}
+
 impl Move {
+	pub fn new(board: u8, cell: u8) -> Result<Self, MoveErr> {
+		if board > 8 {
+			Err(MoveErr::InvalidBoard)
+		} else if cell > 8 {
+			Err(MoveErr::InvalidCell)
+		} else {
+			Ok(Self { board, cell })
+		}
+	}
+
+	/// Inverse of [`Move::index`]
+	pub fn from_index(index: usize) -> Option<Self> {
+		(index < 81).then_some(Self {
+			board: (index / 9) as u8,
+			cell: (index % 9) as u8,
+		})
+	}
+
+	pub fn board(self) -> u8 {
+		self.board
+	}
+
+	pub fn cell(self) -> u8 {
+		self.cell
+	}
+
+	/// Index of this square in [`State::squares`]
+	pub fn index(self) -> usize {
+		self.board as usize * 9 + self.cell as usize
+	}
+
+	pub fn coord(self) -> Coord {
+		Coord {
+			row: self.board / 3 * 3 + self.cell / 3,
+			col: self.board % 3 * 3 + self.cell % 3,
+		}
+	}
+}
+
+impl Coord {
 	pub fn new(row: u8, col: u8) -> Result<Self, MoveErr> {
-		if !(0..=8).contains(&row) {
+		if row > 8 {
 			Err(MoveErr::InvalidRow)
-		} else if !(0..=8).contains(&col) {
+		} else if col > 8 {
 			Err(MoveErr::InvalidColumn)
 		} else {
-			Ok(Self { row, column: col })
+			Ok(Self { row, col })
 		}
 	}
 
@@ -46,18 +250,447 @@ impl Move {
 	}
 
 	pub fn col(self) -> u8 {
-		self.column
+		self.col
+	}
+
+	pub fn to_move(self) -> Move {
+		Move {
+			board: self.row / 3 * 3 + self.col / 3,
+			cell: self.row % 3 * 3 + self.col % 3,
+		}
+	}
+}
+
+impl From<Move> for Coord {
+	fn from(mv: Move) -> Self {
+		mv.coord()
+	}
+}
+
+impl From<Coord> for Move {
+	fn from(coord: Coord) -> Self {
+		coord.to_move()
+	}
+}
+
+impl fmt::Display for Move {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}{}", (b'a' + self.board) as char, self.cell + 1)
+	}
+}
+
+impl FromStr for Move {
+	type Err = MoveErr;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let &[board, cell] = s.as_bytes() else {
+			return Err(MoveErr::InvalidNotation);
+		};
+
+		if !board.is_ascii_lowercase() || !cell.is_ascii_digit() {
+			return Err(MoveErr::InvalidNotation);
+		}
+
+		Move::new(board - b'a', (cell - b'0').wrapping_sub(1))
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct State {
+	/// The board the side to move must play in, 0..=8, or 9 for any board. Only states with
+	/// `active <= 9` can be written as UTT strings, see [`Violation::ActiveOutOfRange`].
 	pub active: u8,
 	pub squares: [Square; 81],
 	pub last_move: Option<Move>,
 }
 
-fn _parse<'a>() -> impl Parser<'a, &'a str, State, extra::Err<Rich<'a, char>>> {
+impl Default for State {
+	/// The empty board with X to move anywhere
+	fn default() -> Self {
+		State {
+			active: 9,
+			squares: [Square::Empty; 81],
+			last_move: None,
+		}
+	}
+}
+
+/// What [`State::undo`] needs to take back a move made with [`State::play`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+	mv: Move,
+	active: u8,
+	last_move: Option<Move>,
+}
+
+impl State {
+	/// The 9 squares of a sub-board, in cell order
+	pub fn board(&self, board: u8) -> &[Square; 9] {
+		let start = board as usize * 9;
+		self.squares[start..start + 9].try_into().unwrap()
+	}
+
+	pub fn board_status(&self, board: u8) -> BoardStatus {
+		let sqs = self.board(board);
+
+		match line_winner(sqs) {
+			Some(Square::X) => BoardStatus::XWon,
+			Some(Square::O) => BoardStatus::OWon,
+			_ if sqs.contains(&Square::Empty) => BoardStatus::Open,
+			_ => BoardStatus::Drawn,
+		}
+	}
+
+	fn is_closed(&self, board: u8) -> bool {
+		self.board_status(board).is_closed()
+	}
+
+	/// Status of every sub-board, laid out as a 3x3 board in board order
+	pub fn meta_board(&self) -> [BoardStatus; 9] {
+		std::array::from_fn(|board| self.board_status(board as u8))
+	}
+
+	/// The meta-board as marks, with won boards as the winner's mark and any other board as empty
+	fn meta_marks(&self) -> [Square; 9] {
+		self.meta_board().map(|status| match status {
+			BoardStatus::XWon => Square::X,
+			BoardStatus::OWon => Square::O,
+			_ => Square::Empty,
+		})
+	}
+
+	pub fn result(&self) -> GameResult {
+		let meta = self.meta_board();
+
+		match line_winner(&self.meta_marks()) {
+			Some(Square::X) => GameResult::XWins,
+			Some(Square::O) => GameResult::OWins,
+			_ if meta.iter().all(|status| status.is_closed()) => GameResult::Draw,
+			_ => GameResult::Ongoing,
+		}
+	}
+
+	/// Whether the side to move may play on any open board, either because `active` is 9 or because
+	/// the board it points to is closed
+	pub fn is_free_move(&self) -> bool {
+		self.active > 8 || self.is_closed(self.active)
+	}
+
+	/// All legal moves in this position, in [`Move::index`] order, there are none once the game is
+	/// decided
+	pub fn legal_moves(&self) -> impl Iterator<Item = Move> + '_ {
+		let free = self.is_free_move();
+		let over = self.result() != GameResult::Ongoing;
+
+		(0..9)
+			.filter(move |&board| {
+				if over {
+					false
+				} else if free {
+					!self.is_closed(board)
+				} else {
+					board == self.active
+				}
+			})
+			.flat_map(move |board| {
+				(0..9)
+					.map(move |cell| Move { board, cell })
+					.filter(|mv| self.squares[mv.index()] == Square::Empty)
+			})
+	}
+
+	pub fn is_legal(&self, mv: Move) -> bool {
+		let board_ok = if self.is_free_move() {
+			!self.is_closed(mv.board)
+		} else {
+			mv.board == self.active
+		};
+
+		board_ok
+			&& self.squares[mv.index()] == Square::Empty
+			&& self.result() == GameResult::Ongoing
+	}
+
+	/// The mark of the side to move. This is the opposite of the mark on the last move when there is
+	/// one, otherwise it's inferred from the number of marks on the board with X moving first
+	pub fn side_to_move(&self) -> Square {
+		if let Some(mv) = self.last_move {
+			match self.squares[mv.index()] {
+				Square::X => return Square::O,
+				Square::O => return Square::X,
+				Square::Empty => {}
+			}
+		}
+
+		let count = |sq| self.squares.iter().filter(|&&s| s == sq).count();
+		if count(Square::X) > count(Square::O) {
+			Square::O
+		} else {
+			Square::X
+		}
+	}
+
+	/// Checks that this position can be reached by legal play from the empty board, returning every
+	/// violation found. This can't catch everything, e.g. a move played into a closed board is only
+	/// caught when it was the last move.
+	pub fn validate(&self) -> Vec<Violation> {
+		let mut violations = Vec::new();
+
+		let count = |sq| self.squares.iter().filter(|&&s| s == sq).count();
+		let (x, o) = (count(Square::X), count(Square::O));
+		let counts_ok = x == o || x == o + 1;
+		if !counts_ok {
+			violations.push(Violation::MarkCount { x, o });
+		}
+
+		for board in 0..9 {
+			let sqs = self.board(board);
+			if has_line(sqs, Square::X) && has_line(sqs, Square::O) {
+				violations.push(Violation::BoardWonTwice { board });
+			}
+		}
+
+		let active_ok = if self.active > 9 {
+			violations.push(Violation::ActiveOutOfRange {
+				active: self.active,
+			});
+			false
+		} else if self.active < 9 && self.is_closed(self.active) {
+			violations.push(Violation::ActiveClosed { board: self.active });
+			false
+		} else {
+			true
+		};
+
+		if let Some(mv) = self.last_move {
+			let moved = if x > o { Square::X } else { Square::O };
+			match self.squares[mv.index()] {
+				Square::Empty => violations.push(Violation::LastMoveEmpty),
+				mark if counts_ok && mark != moved => violations.push(Violation::LastMoveWrongSide),
+				_ => {}
+			}
+
+			let expected = if self.is_closed(mv.cell) { 9 } else { mv.cell };
+			if active_ok && self.active != expected {
+				violations.push(Violation::ActiveMismatch { expected });
+			}
+		}
+
+		let meta = self.meta_marks();
+		let (x_won, o_won) = (has_line(&meta, Square::X), has_line(&meta, Square::O));
+		let over_before_last = self.last_move.is_some_and(|mv| {
+			let mut prev = self.clone();
+			prev.squares[mv.index()] = Square::Empty;
+			prev.result() != GameResult::Ongoing
+		});
+
+		// The winner must have made the last move
+		if (x_won && o_won)
+			|| (counts_ok && x_won && x == o)
+			|| (counts_ok && o_won && x > o)
+			|| over_before_last
+		{
+			violations.push(Violation::PlayAfterGameOver);
+		}
+
+		violations
+	}
+
+	/// Plays a move for the side to move, sending the opponent to the board matching the cell that
+	/// was played in, or giving them a free move if that board is closed
+	pub fn play(&mut self, mv: Move) -> Result<Undo, MoveErr> {
+		if !self.is_legal(mv) {
+			return Err(MoveErr::Illegal);
+		}
+
+		let undo = Undo {
+			mv,
+			active: self.active,
+			last_move: self.last_move,
+		};
+
+		self.squares[mv.index()] = self.side_to_move();
+		self.last_move = Some(mv);
+		self.active = if self.is_closed(mv.cell) { 9 } else { mv.cell };
+
+		Ok(undo)
+	}
+
+	/// Takes back the move that produced `undo`, which must be the last move played
+	pub fn undo(&mut self, undo: Undo) {
+		self.squares[undo.mv.index()] = Square::Empty;
+		self.active = undo.active;
+		self.last_move = undo.last_move;
+	}
+}
+
+impl fmt::Display for State {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		debug_assert!(self.active <= 9, "active board {} is above 9", self.active);
+		write!(f, "{}", self.active)?;
+
+		for board in self.squares.chunks_exact(9) {
+			write!(f, "/")?;
+
+			if f.alternate() {
+				for sq in board {
+					write!(f, "{}", sq.to_char())?;
+				}
+				continue;
+			}
+
+			for run in board.chunk_by(|a, b| a == b) {
+				match run.len() {
+					1 => write!(f, "{}", run[0].to_char())?,
+					n => write!(f, "{n}{}", run[0].to_char())?,
+				}
+			}
+		}
+
+		if let Some(mv) = self.last_move {
+			write!(f, "/{mv}")?;
+		}
+
+		if f.sign_plus() {
+			match self.side_to_move() {
+				Square::O => write!(f, " o")?,
+				_ => write!(f, " x")?,
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Displays a [`State`] followed by its side to move, see [`State::with_side`]
+#[derive(Debug, Clone, Copy)]
+pub struct WithSide<'a>(&'a State);
+
+impl State {
+	/// The UTT string ending with ` x` or ` o` for the side to move, the alternate flag (`{:#}`)
+	/// still writes every square out
+	pub fn with_side(&self) -> WithSide<'_> {
+		WithSide(self)
+	}
+}
+
+impl fmt::Display for WithSide<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match f.alternate() {
+			true => write!(f, "{:+#}", self.0),
+			false => write!(f, "{:+}", self.0),
+		}
+	}
+}
+
+/// Error produced when a UTT string, or a line of text containing one, fails to parse, spans are
+/// byte offsets into the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+	/// The leading active board field is not a single digit followed by a slash
+	ActiveBoard { span: Range<usize> },
+	/// A board does not contain exactly 9 squares
+	SquareCount { found: usize, span: Range<usize> },
+	/// A run has a length of 0 or its length is not followed by a square
+	Run { span: Range<usize> },
+	/// The trailing move is not of the form [a..=i][1..=9]
+	LastMove { span: Range<usize> },
+	/// The trailing move is well formed but points outside of the grid
+	InvalidMove { err: MoveErr, span: Range<usize> },
+	/// The stated side to move isn't `x` or `o`, or contradicts the position
+	SideToMove { span: Range<usize> },
+	/// The string is well formed but the position can't be reached, only produced by
+	/// [`parse_strict`]
+	Unreachable {
+		violations: Vec<Violation>,
+		span: Range<usize>,
+	},
+	/// Input continues after an otherwise complete UTT string
+	TrailingInput { span: Range<usize> },
+	/// A character that can't appear at this position
+	Unexpected {
+		found: Option<char>,
+		span: Range<usize>,
+	},
+}
+
+impl ParseError {
+	pub fn span(&self) -> Range<usize> {
+		match self {
+			Self::ActiveBoard { span }
+			| Self::SquareCount { span, .. }
+			| Self::Run { span }
+			| Self::LastMove { span }
+			| Self::InvalidMove { span, .. }
+			| Self::SideToMove { span }
+			| Self::Unreachable { span, .. }
+			| Self::TrailingInput { span }
+			| Self::Unexpected { span, .. } => span.clone(),
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let Range { start, end } = self.span();
+
+		match self {
+			Self::ActiveBoard { .. } => write!(f, "invalid active board at {start}..{end}"),
+			Self::SquareCount { found, .. } => write!(
+				f,
+				"board at {start}..{end} must have exactly 9 squares, got: {found}"
+			),
+			Self::Run { .. } => write!(f, "invalid run at {start}..{end}"),
+			Self::LastMove { .. } => write!(f, "invalid last move at {start}..{end}"),
+			Self::InvalidMove { err, .. } => write!(f, "{err} at {start}..{end}"),
+			Self::SideToMove { .. } => {
+				write!(f, "invalid side to move at {start}..{end}")
+			}
+			Self::Unreachable { violations, .. } => {
+				write!(f, "unreachable position")?;
+				for (i, violation) in violations.iter().enumerate() {
+					write!(f, "{} {violation}", if i == 0 { ":" } else { "," })?;
+				}
+				Ok(())
+			}
+			Self::TrailingInput { .. } => write!(f, "unexpected trailing input at {start}..{end}"),
+			Self::Unexpected { found: Some(c), .. } => {
+				write!(f, "unexpected character {c:?} at {start}..{end}")
+			}
+			Self::Unexpected { found: None, .. } => write!(f, "unexpected end of input"),
+		}
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+impl<'a> Error<'a, &'a str> for ParseError {
+	fn merge(self, other: Self) -> Self {
+		// Keep whichever error says more about what went wrong
+		match self {
+			Self::Unexpected { .. } => other,
+			_ => self,
+		}
+	}
+}
+
+// Generic over the label so the text parsers used by the protocol grammars work too
+impl<'a, L> LabelError<'a, &'a str, L> for ParseError {
+	fn expected_found<E: IntoIterator<Item = L>>(
+		_expected: E,
+		found: Option<MaybeRef<'a, char>>,
+		span: SimpleSpan,
+	) -> Self {
+		Self::Unexpected {
+			found: found.as_deref().copied(),
+			span: span.into_range(),
+		}
+	}
+}
+
+/// The UTT string grammar without the end of input, so it can be embedded in other grammars
+pub(crate) fn state_parser<'a>() -> impl Parser<'a, &'a str, State, extra::Err<ParseError>> + Clone
+{
 	let digit = one_of('0'..='9').map(|c: char| c.to_digit(10).unwrap() as usize);
 	let slash = just('/');
 
@@ -71,74 +704,489 @@ fn _parse<'a>() -> impl Parser<'a, &'a str, State, extra::Err<Rich<'a, char>>> {
 		cell.map(|c| vec![c]),
 		digit
 			.clone()
-			.then(cell)
-			.map(|(run_len, cell)| vec![cell; run_len]),
+			.then(cell.or_not())
+			.try_map(|(run_len, cell), span: SimpleSpan| match cell {
+				Some(cell) if run_len > 0 => Ok(vec![cell; run_len]),
+				_ => Err(ParseError::Run {
+					span: span.into_range(),
+				}),
+			}),
 	));
 
-	let row = run
-		.repeated()
-		.at_least(1)
-		.collect()
-		.try_map(|runs: Vec<Vec<Square>>, span| {
-			let cells: Vec<_> = runs.into_iter().flatten().collect();
-
-			if cells.len() != 9 {
-				Err(Rich::custom(
-					span,
-					format!("Board must have exactly 9 squares, got: {}", cells.len()),
-				))
-			} else {
-				Ok(cells)
-			}
-		});
+	let row =
+		run.repeated()
+			.at_least(1)
+			.collect()
+			.try_map(|runs: Vec<Vec<Square>>, span: SimpleSpan| {
+				let cells: Vec<_> = runs.into_iter().flatten().collect();
 
-	let active_brd = digit.clone().then_ignore(slash);
+				if cells.len() != 9 {
+					Err(ParseError::SquareCount {
+						found: cells.len(),
+						span: span.into_range(),
+					})
+				} else {
+					Ok(cells)
+				}
+			});
+
+	let active_brd = digit
+		.clone()
+		.then_ignore(slash)
+		.map_err(|e: ParseError| ParseError::ActiveBoard { span: e.span() });
 	let boards = row
 		.separated_by(slash)
 		.exactly(9)
 		.collect::<Vec<Vec<Square>>>();
 
-	let last_move = slash
-		.or_not()
-		.ignore_then(one_of('a'..='i').map(|c: char| c as u32 - b'a' as u32))
-		.then(digit.clone())
-		.map(|(board, index)| Move::new(board as u8, index as u8).unwrap())
+	// The ranges are wider than the format allows so that out of range moves are reported as such
+	let mv = one_of('a'..='z')
+		.map_with(|c: char, e| (c as u8 - b'a', e.span()))
+		.then(digit.clone().map_with(|index, e| (index, e.span())))
+		.try_map(|((board, board_span), (index, index_span)), _| {
+			// Cells are numbered 1..=9 in the format but 0..=8 in a `Move`, 0 wraps out of range
+			Move::new(board, (index as u8).wrapping_sub(1)).map_err(|err| {
+				let span: SimpleSpan = match err {
+					MoveErr::InvalidBoard => board_span,
+					_ => index_span,
+				};
+
+				ParseError::InvalidMove {
+					err,
+					span: span.into_range(),
+				}
+			})
+		});
+
+	// Without the slash a bad move is just as likely to be trailing garbage
+	let last_move = choice((
+		slash.ignore_then(mv.clone().map_err(|e: ParseError| match e {
+			ParseError::InvalidMove { .. } => e,
+			_ => ParseError::LastMove { span: e.span() },
+		})),
+		mv,
+	))
+	.or_not();
+
+	// Only a whole word, so that grammars embedding this one can follow it with words like `other`
+	let side = just(' ')
+		.ignore_then(
+			choice((just('x').to(Square::X), just('o').to(Square::O)))
+				.map_with(|side, e| (side, e.span()))
+				.then_ignore(
+					any()
+						.filter(|c: &char| !c.is_alphanumeric())
+						.ignored()
+						.or(end())
+						.rewind(),
+				)
+				.map_err(|e: ParseError| ParseError::SideToMove { span: e.span() }),
+		)
 		.or_not();
 
-	active_brd
-		.then(boards)
-		.then(last_move)
-		.then_ignore(end())
-		.map(|((active, boards), last_move)| State {
-			active: active as u8,
-			squares: boards
-				.into_iter()
-				.flatten()
-				.collect::<Vec<Square>>()
-				.try_into()
-				.unwrap(),
-			last_move,
+	active_brd.then(boards).then(last_move).then(side).try_map(
+		|(((active, boards), last_move), side), _| {
+			let state = State {
+				active: active as u8,
+				squares: boards
+					.into_iter()
+					.flatten()
+					.collect::<Vec<Square>>()
+					.try_into()
+					.unwrap(),
+				last_move,
+			};
+
+			match side {
+				Some((side, span)) if side != state.side_to_move() => {
+					let span: SimpleSpan = span;
+					Err(ParseError::SideToMove {
+						span: span.into_range(),
+					})
+				}
+				_ => Ok(state),
+			}
+		},
+	)
+}
+
+fn _parse<'a>() -> impl Parser<'a, &'a str, State, extra::Err<ParseError>> {
+	state_parser()
+		.then_ignore(end().map_err(|e: ParseError| ParseError::TrailingInput { span: e.span() }))
+}
+
+/// Parses a UTT string into a [`State`], see the module documentation for the format
+pub fn parse(input: &str) -> Result<State, ParseError> {
+	// Wrapper around chumsky parser so we can change it later in a non-breaking way
+	_parse()
+		.parse(input)
+		.into_result()
+		.map_err(|errs| errs.into_iter().next().unwrap())
+}
+
+/// Like [`parse`] but also rejects positions that fail [`State::validate`]
+pub fn parse_strict(input: &str) -> Result<State, ParseError> {
+	let state = parse(input)?;
+	let violations = state.validate();
+
+	if violations.is_empty() {
+		Ok(state)
+	} else {
+		Err(ParseError::Unreachable {
+			violations,
+			span: 0..input.len(),
 		})
+	}
 }
 
-// Wrapper around chumsky parser so we can change it later in a non-breaking way
-fn parse(input: &str) -> Result<State, Vec<(RangeInclusive<usize>, String)>> {
-	let res = _parse().parse(input);
-	if res.has_errors() {
-		let errs = res
-			.into_errors()
-			.into_iter()
-			.map(|e| {
-				let sp = e.span();
-				let span = sp.start..=sp.end;
-				let reason = e.into_reason().to_string();
+impl FromStr for State {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		parse(s)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::Lcg;
+
+	const EMPTY: &str = "9/9_/9_/9_/9_/9_/9_/9_/9_/9_";
+
+	#[test]
+	fn parses_runs_and_last_move() {
+		let state: State = "4/X8_/9_/9_/9_/2O7_/9_/9_/9_/9_/a1".parse().unwrap();
+
+		assert_eq!(state.active, 4);
+		assert_eq!(state.squares[0], Square::X);
+		assert_eq!(state.squares[36..38], [Square::O; 2]);
+		assert_eq!(state.last_move, Some(Move::new(0, 0).unwrap()));
+	}
+
+	#[test]
+	fn display_round_trips() {
+		let mut rng = Lcg::new(0x2545_f491_4f6c_dd1d);
+		let mut next = |n| rng.next(n) as u8;
+
+		for _ in 0..500 {
+			let mut squares = [Square::Empty; 81];
+			// Bias towards long runs so compression gets exercised
+			let density = next(4);
+			for sq in &mut squares {
+				if next(4) < density {
+					*sq = [Square::X, Square::O][next(2) as usize];
+				}
+			}
+
+			let state = State {
+				active: next(10),
+				squares,
+				last_move: (next(2) == 0).then(|| Move::new(next(9), next(9)).unwrap()),
+			};
+
+			assert_eq!(parse(&state.to_string()).unwrap(), state);
+			assert_eq!(parse(&format!("{state:#}")).unwrap(), state);
+		}
+
+		assert_eq!(parse(EMPTY).unwrap().to_string(), EMPTY);
+		assert_eq!(
+			parse("4/XXO6_/9_/9_/9_/9_/9_/9_/9_/9_a1")
+				.unwrap()
+				.to_string(),
+			"4/2XO6_/9_/9_/9_/9_/9_/9_/9_/9_/a1"
+		);
+	}
+
+	#[test]
+	fn coordinates_agree() {
+		for index in 0..81 {
+			let mv = Move::from_index(index).unwrap();
+			let coord = mv.coord();
+
+			assert_eq!(mv.index(), index);
+			assert_eq!(Move::from(coord), mv);
+			assert_eq!(Coord::new(coord.row(), coord.col()), Ok(coord));
+			assert_eq!(mv.to_string().parse(), Ok(mv));
+		}
+
+		let centre: Move = "e5".parse().unwrap();
+		assert_eq!((centre.board(), centre.cell(), centre.index()), (4, 4, 40));
+		assert_eq!(centre.coord(), Coord::new(4, 4).unwrap());
+
+		// Top right cell of the top left board
+		let mv: Move = "a3".parse().unwrap();
+		assert_eq!(mv.coord(), Coord::new(0, 2).unwrap());
+		assert_eq!(Move::from(Coord::new(3, 0).unwrap()).to_string(), "d1");
+		assert_eq!(Move::from(Coord::new(8, 8).unwrap()).to_string(), "i9");
+
+		assert_eq!("j1".parse::<Move>(), Err(MoveErr::InvalidBoard));
+		assert_eq!("a0".parse::<Move>(), Err(MoveErr::InvalidCell));
+		assert_eq!("a10".parse::<Move>(), Err(MoveErr::InvalidNotation));
+		assert_eq!(Move::from_index(81), None);
+	}
+
+	#[test]
+	fn legal_moves() {
+		let moves = |s: &str| {
+			parse(s)
+				.unwrap()
+				.legal_moves()
+				.map(|mv| mv.to_string())
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(parse(EMPTY).unwrap().legal_moves().count(), 81);
+		assert_eq!(
+			moves("4/9_/9_/9_/9_/X3_O4_/9_/9_/9_/9_"),
+			["e2", "e3", "e4", "e6", "e7", "e8", "e9"]
+		);
+
+		// Board e is won, so being sent there is a free move on the boards that are still open
+		let state = parse("4/9_/9_/9_/9_/3X6_/9_/9_/9_/5OX3O/c5").unwrap();
+		assert!(state.is_free_move());
+		assert_eq!(state.legal_moves().count(), 9 * 7);
+		assert!(state.legal_moves().all(|mv| ![4, 8].contains(&mv.board())));
+		assert!(!state.is_legal("e4".parse().unwrap()));
+		assert!(state.is_legal("a1".parse().unwrap()));
+
+		// A full board that nobody won is closed as well
+		let state = parse("8/9_/9_/9_/9_/9_/9_/9_/9_/XOXXOOOXX").unwrap();
+		assert!(state.is_free_move());
+		assert_eq!(state.legal_moves().count(), 72);
+
+		let state = parse("0/X8_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+		assert!(!state.is_legal("a1".parse().unwrap()));
+		assert!(!state.is_legal("b1".parse().unwrap()));
+		assert!(
+			state
+				.legal_moves()
+				.eq(state.legal_moves().filter(|&mv| state.is_legal(mv)))
+		);
+	}
+
+	#[test]
+	fn play_and_undo() {
+		let mut state = parse(EMPTY).unwrap();
+		let start = state.clone();
+
+		let first = state.play("e5".parse().unwrap()).unwrap();
+		assert_eq!(state.to_string(), "4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5");
+		assert_eq!(state.play("a1".parse().unwrap()), Err(MoveErr::Illegal));
+		assert_eq!(state.play("e5".parse().unwrap()), Err(MoveErr::Illegal));
 
-				(span, reason)
+		let second = state.play("e1".parse().unwrap()).unwrap();
+		assert_eq!(state.to_string(), "0/9_/9_/9_/9_/O3_X4_/9_/9_/9_/9_/e1");
+
+		state.undo(second);
+		state.undo(first);
+		assert_eq!(state, start);
+
+		// Sending the opponent to a closed board gives them a free move
+		let mut state = parse("0/X8_/9_/9_/9_/9_/9_/9_/9_/3O6_").unwrap();
+		let undo = state.play("a2".parse().unwrap()).unwrap();
+		assert_eq!(state.active, 1);
+		state.undo(undo);
+		state.play("a9".parse().unwrap()).unwrap();
+		assert_eq!(state.active, 9);
+		assert_eq!(state.board(0)[8], Square::X);
+	}
+
+	#[test]
+	fn outcomes() {
+		let state = parse("9/XOX3_OXO/9_/9_/9_/9_/9_/9_/9_/XOXXOOOXX").unwrap();
+		assert_eq!(state.board_status(0), BoardStatus::Open);
+		assert_eq!(state.board_status(8), BoardStatus::Drawn);
+		assert_eq!(state.result(), GameResult::Ongoing);
+
+		// X wins boards a, e and i, the diagonal of the meta-board
+		let state = parse("9/3X6_/O8_/9_/9_/X3_X3_X/9_/O8_/9_/2_X_X_X2_").unwrap();
+		assert_eq!(
+			state.meta_board(),
+			[
+				BoardStatus::XWon,
+				BoardStatus::Open,
+				BoardStatus::Open,
+				BoardStatus::Open,
+				BoardStatus::XWon,
+				BoardStatus::Open,
+				BoardStatus::Open,
+				BoardStatus::Open,
+				BoardStatus::XWon,
+			]
+		);
+		assert_eq!(state.result(), GameResult::XWins);
+		assert_eq!(state.legal_moves().count(), 0);
+		assert!(!state.is_legal("b1".parse().unwrap()));
+
+		let state = parse("9/3O6_/3O6_/3O6_/9_/9_/9_/9_/9_/9_").unwrap();
+		assert_eq!(state.board_status(1), BoardStatus::OWon);
+		assert_eq!(state.result(), GameResult::OWins);
+
+		// Every board closed and no line of boards for either side
+		let x = "3X6_";
+		let o = "3O6_";
+		let state = parse(&format!("9/{x}/{o}/{x}/{x}/{o}/{o}/{o}/{x}/{x}")).unwrap();
+		assert_eq!(state.result(), GameResult::Draw);
+	}
+
+	#[test]
+	fn side_to_move() {
+		let state = parse(EMPTY).unwrap();
+		assert_eq!(state.side_to_move(), Square::X);
+		assert_eq!(state.with_side().to_string(), format!("{EMPTY} x"));
+		assert_eq!(format!("{state:+}"), format!("{EMPTY} x"));
+		assert_eq!(parse(&format!("{EMPTY} x")), Ok(state));
+
+		let state = parse("4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_ o").unwrap();
+		assert_eq!(state.side_to_move(), Square::O);
+		assert_eq!(
+			parse("4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5 x"),
+			Err(ParseError::SideToMove { span: 35..36 })
+		);
+
+		// The last move decides when the counts alone can't
+		let state = parse("0/X8_/9_/9_/9_/O8_/9_/9_/9_/9_/e1").unwrap();
+		assert_eq!(state.side_to_move(), Square::X);
+		assert_eq!(parse(&state.with_side().to_string()), Ok(state.clone()));
+		let full = format!("{:#}", state.with_side());
+		assert!(full.starts_with("0/X________/") && full.ends_with("/e1 x"));
+		assert_eq!(parse(&full), Ok(state));
+		assert_eq!(
+			parse(&format!("{EMPTY} X")),
+			Err(ParseError::SideToMove { span: 29..30 })
+		);
+	}
+
+	#[test]
+	fn validation() {
+		let violations = |s: &str| parse(s).unwrap().validate();
+
+		assert_eq!(violations(EMPTY), []);
+		assert_eq!(violations("0/9_/9_/9_/9_/O3_X4_/9_/9_/9_/9_/e1"), []);
+		assert_eq!(
+			violations("9/OOOOO4_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			[Violation::MarkCount { x: 0, o: 5 }]
+		);
+		assert_eq!(
+			violations("4/9_/9_/9_/9_/9_/9_/9_/9_/9_/e5"),
+			[Violation::LastMoveEmpty]
+		);
+		assert_eq!(
+			violations("1/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5"),
+			[Violation::ActiveMismatch { expected: 4 }]
+		);
+		assert_eq!(
+			violations("0/3X2O4_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			[Violation::ActiveClosed { board: 0 }]
+		);
+		assert_eq!(
+			violations("9/3X3O3_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			[Violation::BoardWonTwice { board: 0 }]
+		);
+
+		// O played on after X had already won a, e and i
+		let won = "9/3X6_/OO_OO4_/9_/9_/X3_X3_X/9_/OO_OO4_/9_/2_X_X_X2_";
+		assert_eq!(violations(won), []);
+		assert_eq!(
+			violations("9/3X6_/OO_OO4_/O8_/9_/X3_X3_X/9_/OO_OO4_/9_/2_X_X_X2_"),
+			[Violation::PlayAfterGameOver]
+		);
+
+		assert!(parse_strict(won).is_ok());
+		assert_eq!(
+			parse_strict("9/OO7_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			Err(ParseError::Unreachable {
+				violations: vec![Violation::MarkCount { x: 0, o: 2 }],
+				span: 0..30,
 			})
-			.collect();
+		);
+	}
 
-		Err(errs)
-	} else {
-		Ok(res.into_output().unwrap())
+	#[test]
+	fn error_kinds() {
+		let kind = |s: &str| parse(s).unwrap_err();
+
+		assert_eq!(parse(EMPTY).unwrap().squares, [Square::Empty; 81]);
+		assert_eq!(
+			kind("x/9_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			ParseError::ActiveBoard { span: 0..1 }
+		);
+		assert_eq!(
+			kind("9/8_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			ParseError::SquareCount {
+				found: 8,
+				span: 2..4
+			}
+		);
+		assert_eq!(
+			kind("9/0X9_/9_/9_/9_/9_/9_/9_/9_/9_"),
+			ParseError::Run { span: 2..4 }
+		);
+		assert_eq!(
+			kind("9/9_/9_/9_/9_/9_/9_/9_/9_/9_/a"),
+			ParseError::LastMove { span: 30..30 }
+		);
+		assert_eq!(
+			kind("9/9_/9_/9_/9_/9_/9_/9_/9_/9_/a1?"),
+			ParseError::TrailingInput { span: 31..32 }
+		);
+		assert_eq!(
+			kind("9/9_/9_/9_/9_/9_/9_/9_/9_/9_/a0"),
+			ParseError::InvalidMove {
+				err: MoveErr::InvalidCell,
+				span: 30..31
+			}
+		);
+		assert_eq!(
+			kind("9/9_/9_/9_/9_/9_/9_/9_/9_/9_/j1"),
+			ParseError::InvalidMove {
+				err: MoveErr::InvalidBoard,
+				span: 29..30
+			}
+		);
+	}
+
+	#[test]
+	fn never_panics() {
+		const ALPHABET: &[u8] = b"0123456789XO_/aeijoxz ?";
+		let mut rng = Lcg::new(0x9e37_79b9_7f4a_7c15);
+		let mut next = |n| rng.next(n) as u8;
+
+		// Arbitrary strings, mostly made of characters the grammar cares about
+		for _ in 0..5_000 {
+			let len = next(64) as usize;
+			let input: String = (0..len)
+				.map(|_| match next(16) {
+					0 => char::from_u32(next(255) as u32 + 1).unwrap(),
+					_ => ALPHABET[next(ALPHABET.len() as u64) as usize] as char,
+				})
+				.collect();
+
+			let _ = parse(&input);
+		}
+
+		// Small mutations of valid strings get much deeper into the grammar
+		let valid = "4/2XO6_/X8_/9_/O8_/9_/9_/3X6_/9_/9_/i9".as_bytes();
+		for _ in 0..5_000 {
+			let mut input = valid.to_vec();
+			for _ in 0..=next(3) {
+				let at = next(input.len() as u64) as usize;
+				match next(3) {
+					0 => input[at] = ALPHABET[next(ALPHABET.len() as u64) as usize],
+					1 => _ = input.remove(at),
+					_ => input.insert(at, ALPHABET[next(ALPHABET.len() as u64) as usize]),
+				}
+			}
+
+			let _ = parse(std::str::from_utf8(&input).unwrap());
+		}
+
+		// Every possible trailing move
+		for board in 'a'..='z' {
+			for index in '0'..='9' {
+				let _ = parse(&format!("{EMPTY}/{board}{index}"));
+			}
+		}
 	}
 }
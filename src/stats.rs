@@ -0,0 +1,384 @@
+//! Statistics for deciding whether one engine is stronger than another.
+//!
+//! Results are counted either per game as a [`Wdl`], or per pair of games played from the same
+//! opening with colors swapped as a [`Pentanomial`], which accounts for the correlation between
+//! the two games and gives tighter bounds. Both give an [`EloEstimate`], a likelihood of
+//! superiority and the log-likelihood ratio used by [`Sprt`], computed with the usual normal
+//! approximation on the logistic Elo scale.
+
+use std::fmt;
+
+/// Expected score of a player rated `elo` above its opponent
+pub fn score_from_elo(elo: f64) -> f64 {
+	1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo difference giving an expected score of `score`, infinite for 0 and 1
+pub fn elo_from_score(score: f64) -> f64 {
+	400.0 * (score / (1.0 - score)).log10()
+}
+
+/// The error function, accurate to about 1e-7
+fn erf(x: f64) -> f64 {
+	// Abramowitz and Stegun 7.1.26
+	let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+	let poly = t
+		* (0.254829592
+			+ t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+	(1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+/// Cumulative distribution function of the standard normal distribution
+fn phi(x: f64) -> f64 {
+	0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Sample size, mean score per game and variance of the score per sample, where a sample is a game
+/// or a pair of games
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moments {
+	pub count: f64,
+	pub mean: f64,
+	pub variance: f64,
+}
+
+impl Moments {
+	/// `counts[i]` samples scored `scores[i]`
+	fn new(counts: &[f64], scores: &[f64]) -> Self {
+		let count: f64 = counts.iter().sum();
+		let mean = counts.iter().zip(scores).map(|(n, s)| n * s).sum::<f64>() / count;
+		let variance = counts
+			.iter()
+			.zip(scores)
+			.map(|(n, s)| n * (s - mean).powi(2))
+			.sum::<f64>()
+			/ count;
+
+		Self {
+			count,
+			mean,
+			variance,
+		}
+	}
+}
+
+/// A sample of results, from the point of view of the engine being tested
+pub trait Sample {
+	/// `None` when there are no results yet
+	fn moments(&self) -> Option<Moments>;
+
+	/// Moments with half a result added to every outcome, so that the variance is never 0 and a
+	/// handful of lopsided results can't decide a test
+	fn regularized(&self) -> Moments;
+
+	/// Elo difference with its 95% confidence interval, whose bounds are infinite when it reaches
+	/// a score of 0 or 1
+	fn elo(&self) -> Option<EloEstimate> {
+		let Moments {
+			count,
+			mean,
+			variance,
+		} = self.moments()?;
+		let margin = 1.959964 * (variance / count).sqrt();
+
+		Some(EloEstimate {
+			elo: elo_from_score(mean),
+			lower: elo_from_score((mean - margin).max(0.0)),
+			upper: elo_from_score((mean + margin).min(1.0)),
+		})
+	}
+
+	/// Likelihood of superiority, the probability that the engine is stronger
+	fn los(&self) -> Option<f64> {
+		let Moments {
+			count,
+			mean,
+			variance,
+		} = self.moments()?;
+
+		// Every sample scored the same, so there's no doubt either way
+		if variance == 0.0 {
+			return Some(match mean {
+				0.5 => 0.5,
+				mean if mean > 0.5 => 1.0,
+				_ => 0.0,
+			});
+		}
+
+		Some(phi((mean - 0.5) / (variance / count).sqrt()))
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Wdl {
+	pub wins: u32,
+	pub draws: u32,
+	pub losses: u32,
+}
+
+impl Wdl {
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	fn counts(&self) -> [f64; 3] {
+		[self.losses as f64, self.draws as f64, self.wins as f64]
+	}
+}
+
+const WDL_SCORES: [f64; 3] = [0.0, 0.5, 1.0];
+
+impl Sample for Wdl {
+	fn moments(&self) -> Option<Moments> {
+		(self.games() > 0).then(|| Moments::new(&self.counts(), &WDL_SCORES))
+	}
+
+	fn regularized(&self) -> Moments {
+		Moments::new(&self.counts().map(|n| n + 0.5), &WDL_SCORES)
+	}
+}
+
+/// Results of game pairs, `counts[i]` pairs scored `i` half points out of 4
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pentanomial {
+	pub counts: [u32; 5],
+}
+
+impl Pentanomial {
+	pub fn pairs(&self) -> u32 {
+		self.counts.iter().sum()
+	}
+
+	/// Adds a pair where the engine scored `first` and `second`, each 0, 0.5 or 1
+	pub fn add(&mut self, first: f64, second: f64) {
+		self.counts[((first + second) * 2.0).round() as usize] += 1;
+	}
+}
+
+const PENTANOMIAL_SCORES: [f64; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+impl Sample for Pentanomial {
+	fn moments(&self) -> Option<Moments> {
+		(self.pairs() > 0)
+			.then(|| Moments::new(&self.counts.map(|n| n as f64), &PENTANOMIAL_SCORES))
+	}
+
+	fn regularized(&self) -> Moments {
+		Moments::new(&self.counts.map(|n| n as f64 + 0.5), &PENTANOMIAL_SCORES)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EloEstimate {
+	pub elo: f64,
+	/// Bounds of the 95% confidence interval
+	pub lower: f64,
+	pub upper: f64,
+}
+
+/// Written as `elo [lower, upper]`, since the interval is only symmetric on the score scale
+impl fmt::Display for EloEstimate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{:+.1} [{:+.1}, {:+.1}]",
+			self.elo, self.lower, self.upper
+		)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+	/// Not enough evidence either way
+	Continue,
+	/// The engine is no stronger than `elo0`
+	H0,
+	/// The engine is at least `elo1` stronger
+	H1,
+}
+
+/// Sequential probability ratio test of `elo <= elo0` against `elo >= elo1`, with `alpha` and
+/// `beta` the rates of false positives and false negatives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+	pub elo0: f64,
+	pub elo1: f64,
+	pub alpha: f64,
+	pub beta: f64,
+}
+
+impl Default for Sprt {
+	fn default() -> Self {
+		Self {
+			elo0: 0.0,
+			elo1: 5.0,
+			alpha: 0.05,
+			beta: 0.05,
+		}
+	}
+}
+
+impl Sprt {
+	/// Log-likelihood ratios at which H0 and H1 are accepted
+	pub fn bounds(&self) -> (f64, f64) {
+		(
+			(self.beta / (1.0 - self.alpha)).ln(),
+			((1.0 - self.beta) / self.alpha).ln(),
+		)
+	}
+
+	pub fn llr(&self, sample: &impl Sample) -> f64 {
+		if sample.moments().is_none() {
+			return 0.0;
+		}
+
+		let Moments {
+			count,
+			mean,
+			variance,
+		} = sample.regularized();
+		let (s0, s1) = (score_from_elo(self.elo0), score_from_elo(self.elo1));
+
+		count * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+	}
+
+	pub fn verdict(&self, sample: &impl Sample) -> Verdict {
+		let llr = self.llr(sample);
+		let (lower, upper) = self.bounds();
+
+		if llr <= lower {
+			Verdict::H0
+		} else if llr >= upper {
+			Verdict::H1
+		} else {
+			Verdict::Continue
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(a: f64, b: f64, eps: f64) -> bool {
+		(a - b).abs() < eps
+	}
+
+	#[test]
+	fn elo_and_los() {
+		assert!(close(erf(1.0), 0.842_700_79, 1e-6));
+		assert!(close(erf(-0.5), -0.520_499_88, 1e-6));
+		assert!(close(elo_from_score(score_from_elo(123.0)), 123.0, 1e-9));
+		assert_eq!(elo_from_score(1.0), f64::INFINITY);
+
+		let wdl = Wdl {
+			wins: 60,
+			draws: 20,
+			losses: 20,
+		};
+		let elo = wdl.elo().unwrap();
+		assert!(close(elo.elo, 147.19, 0.01));
+		assert!(elo.lower < elo.elo && elo.elo < elo.upper);
+		assert!(wdl.los().unwrap() > 0.999);
+
+		let even = Wdl {
+			wins: 10,
+			draws: 5,
+			losses: 10,
+		};
+		assert!(close(even.elo().unwrap().elo, 0.0, 1e-9));
+		assert!(close(even.los().unwrap(), 0.5, 1e-9));
+		assert_eq!(Wdl::default().elo(), None);
+	}
+
+	#[test]
+	fn lopsided_samples() {
+		let lost = Wdl {
+			wins: 1,
+			draws: 0,
+			losses: 9,
+		};
+		let elo = lost.elo().unwrap();
+		assert!(close(elo.elo, -381.70, 0.01));
+		assert_eq!(elo.lower, f64::NEG_INFINITY);
+		assert!(elo.upper.is_finite());
+		assert_eq!(elo.to_string(), "-381.7 [-inf, -159.0]");
+
+		let draws = Wdl {
+			wins: 0,
+			draws: 7,
+			losses: 0,
+		};
+		assert_eq!(draws.los(), Some(0.5));
+		assert_eq!(draws.elo().unwrap().to_string(), "+0.0 [+0.0, +0.0]");
+		let swept = Wdl {
+			wins: 4,
+			draws: 0,
+			losses: 0,
+		};
+		assert_eq!(swept.los(), Some(1.0));
+		let pairs = Pentanomial {
+			counts: [3, 0, 0, 0, 0],
+		};
+		assert_eq!(pairs.los(), Some(0.0));
+	}
+
+	#[test]
+	fn pentanomial() {
+		let mut penta = Pentanomial::default();
+		penta.add(1.0, 0.0);
+		penta.add(1.0, 0.5);
+		penta.add(0.5, 0.5);
+		assert_eq!(penta.counts, [0, 0, 2, 1, 0]);
+		assert!(close(penta.moments().unwrap().mean, 7.0 / 12.0, 1e-9));
+
+		// Pairs take out the variance of colors alternating, narrowing the interval
+		let wdl = Wdl {
+			wins: 30,
+			draws: 0,
+			losses: 30,
+		};
+		let pairs = Pentanomial {
+			counts: [0, 0, 30, 0, 0],
+		};
+		let wdl = wdl.elo().unwrap();
+		let pairs = pairs.elo().unwrap();
+		assert!(close(wdl.elo, pairs.elo, 1e-9));
+		assert!(pairs.upper - pairs.lower < wdl.upper - wdl.lower);
+	}
+
+	#[test]
+	fn sprt() {
+		let sprt = Sprt::default();
+		let (lower, upper) = sprt.bounds();
+		assert!(close(lower, -2.944, 1e-3));
+		assert!(close(upper, 2.944, 1e-3));
+
+		assert_eq!(sprt.verdict(&Wdl::default()), Verdict::Continue);
+		let strong = Wdl {
+			wins: 600,
+			draws: 200,
+			losses: 400,
+		};
+		assert_eq!(sprt.verdict(&strong), Verdict::H1);
+		let weak = Wdl {
+			wins: 400,
+			draws: 200,
+			losses: 600,
+		};
+		assert_eq!(sprt.verdict(&weak), Verdict::H0);
+		let close_call = Wdl {
+			wins: 11,
+			draws: 2,
+			losses: 10,
+		};
+		assert_eq!(sprt.verdict(&close_call), Verdict::Continue);
+
+		// Every pair won still needs enough of them
+		let sweep = |pairs| Pentanomial {
+			counts: [0, 0, 0, 0, pairs],
+		};
+		assert_eq!(sprt.verdict(&sweep(10)), Verdict::Continue);
+		assert_eq!(sprt.verdict(&sweep(30)), Verdict::H1);
+	}
+}
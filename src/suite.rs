@@ -0,0 +1,345 @@
+//! Test suites of annotated positions, modelled on EPD.
+//!
+//! Every line is a UTT string followed by operations, each an opcode, its operands and a `;`:
+//!
+//! ```text
+//! 4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5 bm e1 e9; id "corner reply"; ce -20;
+//! ```
+//!
+//! The known opcodes are `bm` and `am`, the best moves and moves to avoid, `id`, a quoted name,
+//! `ce`, an evaluation from the side to move's point of view, and `result`, the game's result
+//! with best play. Other opcodes are kept with their operands as written. [`run`] scores an engine
+//! on the entries with a `bm` or `am`.
+
+use std::{fmt, str::FromStr};
+
+use chumsky::prelude::*;
+
+use crate::{
+	game::{self, escape, mv, string},
+	protocol::{number, param},
+	state::{GameResult, Move, ParseError, State, state_parser},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+	pub state: State,
+	/// `bm`, any of which solves the entry
+	pub best_moves: Vec<Move>,
+	/// `am`, none of which may be played
+	pub avoid_moves: Vec<Move>,
+	/// `id`
+	pub id: Option<String>,
+	/// `ce`
+	pub eval: Option<i32>,
+	/// `result`
+	pub result: Option<GameResult>,
+	/// Other opcodes and their operands
+	pub other: Vec<(String, String)>,
+}
+
+impl Entry {
+	/// Whether the entry is solved by playing `mv`
+	pub fn accepts(&self, mv: Move) -> bool {
+		(self.best_moves.is_empty() || self.best_moves.contains(&mv))
+			&& !self.avoid_moves.contains(&mv)
+	}
+
+	/// Whether the entry says anything about which move to play
+	pub fn is_scored(&self) -> bool {
+		!self.best_moves.is_empty() || !self.avoid_moves.is_empty()
+	}
+}
+
+impl fmt::Display for Entry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.state)?;
+
+		for (opcode, moves) in [("bm", &self.best_moves), ("am", &self.avoid_moves)] {
+			if !moves.is_empty() {
+				write!(f, " {opcode}")?;
+				for mv in moves {
+					write!(f, " {mv}")?;
+				}
+				write!(f, ";")?;
+			}
+		}
+		if let Some(id) = &self.id {
+			write!(f, " id \"{}\";", escape(id))?;
+		}
+		if let Some(eval) = self.eval {
+			write!(f, " ce {eval};")?;
+		}
+		if let Some(result) = self.result {
+			write!(f, " result {result};")?;
+		}
+		for (opcode, operands) in &self.other {
+			match operands.is_empty() {
+				true => write!(f, " {opcode};")?,
+				false => write!(f, " {opcode} {operands};")?,
+			}
+		}
+
+		Ok(())
+	}
+}
+
+type Extra = extra::Err<ParseError>;
+
+#[derive(Clone)]
+enum Op {
+	Bm(Vec<Move>),
+	Am(Vec<Move>),
+	Id(String),
+	Ce(i32),
+	Result(GameResult),
+	Other(String, String),
+}
+
+fn entry<'a>() -> impl Parser<'a, &'a str, Entry, Extra> {
+	let ws = || text::inline_whitespace().at_least(1);
+	let moves = mv().separated_by(ws()).at_least(1).collect::<Vec<_>>();
+
+	let known = ["bm", "am", "id", "ce", "result"];
+	let other = text::ident()
+		.filter(move |opcode: &&str| !known.contains(opcode))
+		.then(
+			ws().ignore_then(none_of(';').repeated().to_slice())
+				.or_not(),
+		)
+		.map(|(opcode, operands): (&str, Option<&str>)| {
+			Op::Other(
+				opcode.to_string(),
+				operands.unwrap_or_default().trim().to_string(),
+			)
+		});
+
+	let operation = choice((
+		param("bm", moves.clone().map(Op::Bm)),
+		param("am", moves.map(Op::Am)),
+		param("id", string().map(Op::Id)),
+		param("ce", number().map(Op::Ce)),
+		param("result", game::result().map(Op::Result)),
+		other,
+	))
+	.then_ignore(text::inline_whitespace())
+	.then_ignore(just(';'));
+
+	state_parser()
+		.then(ws().ignore_then(operation).repeated().collect::<Vec<_>>())
+		.then_ignore(text::inline_whitespace())
+		.then_ignore(end().map_err(|e: ParseError| ParseError::TrailingInput { span: e.span() }))
+		.map(|(state, ops)| {
+			let mut entry = Entry {
+				state,
+				..Entry::default()
+			};
+			for op in ops {
+				match op {
+					Op::Bm(moves) => entry.best_moves = moves,
+					Op::Am(moves) => entry.avoid_moves = moves,
+					Op::Id(id) => entry.id = Some(id),
+					Op::Ce(eval) => entry.eval = Some(eval),
+					Op::Result(result) => entry.result = Some(result),
+					Op::Other(opcode, operands) => entry.other.push((opcode, operands)),
+				}
+			}
+			entry
+		})
+}
+
+impl FromStr for Entry {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		entry()
+			.parse(s)
+			.into_result()
+			.map_err(|errs| errs.into_iter().next().unwrap())
+	}
+}
+
+/// A line of a suite that failed to parse, `line` counts from 1 and `err`'s span is relative to
+/// the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuiteError {
+	pub line: usize,
+	pub err: ParseError,
+}
+
+impl fmt::Display for SuiteError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.err)
+	}
+}
+
+impl std::error::Error for SuiteError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.err)
+	}
+}
+
+/// Parses a suite, one entry per line, skipping blank lines and lines starting with `#`
+pub fn parse_suite(text: &str) -> Result<Vec<Entry>, SuiteError> {
+	text.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+		.map(|(i, line)| line.parse().map_err(|err| SuiteError { line: i + 1, err }))
+		.collect()
+}
+
+/// How an engine did on one entry of a suite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+	/// Index of the entry in the suite
+	pub entry: usize,
+	pub played: Option<Move>,
+	pub solved: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+	/// One per scored entry, in order
+	pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+	pub fn solved(&self) -> usize {
+		self.outcomes
+			.iter()
+			.filter(|outcome| outcome.solved)
+			.count()
+	}
+}
+
+/// Asks `search` for a move in every scored entry of `suite`
+pub fn run(suite: &[Entry], mut search: impl FnMut(&State) -> Option<Move>) -> Report {
+	let outcomes = suite
+		.iter()
+		.enumerate()
+		.filter(|(_, entry)| entry.is_scored())
+		.map(|(i, entry)| {
+			let played = search(&entry.state);
+			Outcome {
+				entry: i,
+				played,
+				solved: played.is_some_and(|mv| entry.accepts(mv)),
+			}
+		})
+		.collect();
+
+	Report { outcomes }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		search::{Heuristic, Limits, Searcher},
+		state::Square,
+		testing::Lcg,
+	};
+
+	const LINE: &str = "4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5 bm e1 e9; am e4; id \"corner \\\"reply\\\"\"; ce -20; \
+		 result 1/2-1/2; c0 some comment; noop;";
+
+	#[test]
+	fn parses_entries() {
+		let entry: Entry = LINE.parse().unwrap();
+
+		let moves = |moves: &[Move]| moves.iter().map(Move::to_string).collect::<Vec<_>>();
+		assert_eq!(moves(&entry.best_moves), ["e1", "e9"]);
+		assert_eq!(moves(&entry.avoid_moves), ["e4"]);
+		assert_eq!(entry.id.as_deref(), Some("corner \"reply\""));
+		assert_eq!(entry.eval, Some(-20));
+		assert_eq!(entry.result, Some(GameResult::Draw));
+		assert_eq!(
+			entry.other,
+			[
+				("c0".to_string(), "some comment".to_string()),
+				("noop".to_string(), String::new())
+			]
+		);
+		assert_eq!(entry.to_string(), LINE);
+
+		// An opcode starting with a side to move letter isn't taken for one
+		let entry: Entry = "9/9_/9_/9_/9_/9_/9_/9_/9_/9_ x omega 1;".parse().unwrap();
+		assert_eq!(entry.other, [("omega".to_string(), "1".to_string())]);
+		let entry: Entry = "9/9_/9_/9_/9_/9_/9_/9_/9_/9_ omega;".parse().unwrap();
+		assert_eq!(entry.other, [("omega".to_string(), String::new())]);
+	}
+
+	#[test]
+	fn reports_errors() {
+		let err = |s: &str| s.parse::<Entry>().unwrap_err();
+		let empty = "9/9_/9_/9_/9_/9_/9_/9_/9_/9_";
+
+		assert!(matches!(
+			err(&format!("{empty} bm e0;")),
+			ParseError::InvalidMove { span, .. } if span == (32..34)
+		));
+		assert!(matches!(
+			err(&format!("{empty} bm;")),
+			ParseError::Unexpected { .. }
+		));
+		assert!(matches!(
+			err(&format!("{empty} ce 1")),
+			ParseError::Unexpected { found: None, .. }
+		));
+		assert!(matches!(
+			err("9/9_"),
+			ParseError::Unexpected { .. } | ParseError::SquareCount { .. }
+		));
+
+		let suite = format!("# tactics\n\n{empty} bm e5;\n");
+		assert_eq!(parse_suite(&suite).unwrap().len(), 1);
+		let suite = format!("{suite}{empty} id \"x\n");
+		assert_eq!(parse_suite(&suite).unwrap_err().line, 4);
+	}
+
+	#[test]
+	fn scores_engines() {
+		// Positions from random games where the side to move can win on the spot
+		let mut rng = Lcg::new(7);
+		let mut suite = vec![];
+		while suite.len() < 8 {
+			let mut state = State::default();
+			while state.result() == GameResult::Ongoing {
+				let winning: Vec<_> = state
+					.legal_moves()
+					.filter(|&mv| {
+						let mut next = state.clone();
+						next.play(mv).unwrap();
+						match state.side_to_move() {
+							Square::X => next.result() == GameResult::XWins,
+							_ => next.result() == GameResult::OWins,
+						}
+					})
+					.collect();
+				if !winning.is_empty() {
+					suite.push(Entry {
+						state: state.clone(),
+						best_moves: winning,
+						..Entry::default()
+					});
+					break;
+				}
+
+				state.play(rng.legal_move(&state).unwrap()).unwrap();
+			}
+		}
+		suite.push(Entry::default());
+
+		let mut searcher = Searcher::new(Heuristic, 1 << 12);
+		let limits = Limits {
+			depth: Some(2),
+			..Limits::default()
+		};
+		let report = run(&suite, |state| searcher.search(state, &limits).best_move);
+		assert_eq!(report.outcomes.len(), 8);
+		assert_eq!(report.solved(), 8);
+
+		let report = run(&suite, |_| None);
+		assert_eq!(report.solved(), 0);
+	}
+}
@@ -0,0 +1,178 @@
+//! The 8 symmetries of the board
+//! A symmetry acts the same way on the meta-board and on every sub-board, which is the same as
+//! acting on the full 9x9 grid. Symmetric positions have the same legal moves (up to the symmetry)
+//! and the same outcome, so [`State::canonical`] can be used to store one representative per
+//! position.
+
+use crate::state::{Move, Square, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+	Identity,
+	/// Clockwise quarter turn
+	Rotate90,
+	Rotate180,
+	Rotate270,
+	/// Mirror left to right
+	FlipHorizontal,
+	/// Mirror top to bottom
+	FlipVertical,
+	/// Mirror along the top left to bottom right diagonal
+	FlipDiagonal,
+	/// Mirror along the top right to bottom left diagonal
+	FlipAntiDiagonal,
+}
+
+impl Symmetry {
+	pub const ALL: [Symmetry; 8] = [
+		Symmetry::Identity,
+		Symmetry::Rotate90,
+		Symmetry::Rotate180,
+		Symmetry::Rotate270,
+		Symmetry::FlipHorizontal,
+		Symmetry::FlipVertical,
+		Symmetry::FlipDiagonal,
+		Symmetry::FlipAntiDiagonal,
+	];
+
+	/// The symmetry undoing this one
+	pub fn inverse(self) -> Symmetry {
+		match self {
+			Symmetry::Rotate90 => Symmetry::Rotate270,
+			Symmetry::Rotate270 => Symmetry::Rotate90,
+			other => other,
+		}
+	}
+
+	/// Maps an index 0..=8 of a 3x3 grid in reading order, i.e. a board or a cell
+	pub fn apply_index(self, index: u8) -> u8 {
+		let (r, c) = (index / 3, index % 3);
+		let (r, c) = match self {
+			Symmetry::Identity => (r, c),
+			Symmetry::Rotate90 => (c, 2 - r),
+			Symmetry::Rotate180 => (2 - r, 2 - c),
+			Symmetry::Rotate270 => (2 - c, r),
+			Symmetry::FlipHorizontal => (r, 2 - c),
+			Symmetry::FlipVertical => (2 - r, c),
+			Symmetry::FlipDiagonal => (c, r),
+			Symmetry::FlipAntiDiagonal => (2 - c, 2 - r),
+		};
+
+		r * 3 + c
+	}
+
+	pub fn apply_move(self, mv: Move) -> Move {
+		Move::new(self.apply_index(mv.board()), self.apply_index(mv.cell())).unwrap()
+	}
+
+	/// Maps an active board, leaving 9 ("any board") alone
+	pub fn apply_active(self, active: u8) -> u8 {
+		if active > 8 {
+			active
+		} else {
+			self.apply_index(active)
+		}
+	}
+}
+
+impl State {
+	pub fn transform(&self, sym: Symmetry) -> State {
+		let mut squares = [Square::Empty; 81];
+		for (i, &sq) in self.squares.iter().enumerate() {
+			squares[sym.apply_move(Move::from_index(i).unwrap()).index()] = sq;
+		}
+
+		State {
+			active: sym.apply_active(self.active),
+			squares,
+			last_move: self.last_move.map(|mv| sym.apply_move(mv)),
+		}
+	}
+
+	/// The smallest of the 8 images of this position along with the symmetry producing it, positions
+	/// that are symmetric to each other have the same canonical form. Images are ordered by their
+	/// squares (empty before X before O), then active board, then last move.
+	pub fn canonical(&self) -> (State, Symmetry) {
+		let key = |state: &State| {
+			let squares = state.squares.map(|sq| match sq {
+				Square::Empty => 0,
+				Square::X => 1,
+				Square::O => 2,
+			});
+
+			(squares, state.active, state.last_move.map(|mv| mv.index()))
+		};
+
+		Symmetry::ALL
+			.into_iter()
+			.map(|sym| (self.transform(sym), sym))
+			.min_by_key(|(state, _)| key(state))
+			.unwrap()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{perft::perft, state::parse};
+
+	const POSITION: &str =
+		"9/2_X3_2OX/4_2X2_X/6_3O/O_O_O4_/2_O2_XOXO/_OX_X_OXO/X2_X2_X2_/XO2X3O2X/2_3X2OXO/h3";
+
+	#[test]
+	fn transforms() {
+		let mv: Move = "a1".parse().unwrap();
+		let images = Symmetry::ALL.map(|sym| sym.apply_move(mv).to_string());
+		assert_eq!(images, ["a1", "c3", "i9", "g7", "c3", "g7", "a1", "i9"]);
+		assert_eq!(
+			Symmetry::Rotate90
+				.apply_move("b6".parse().unwrap())
+				.to_string(),
+			"f8"
+		);
+
+		let state = parse(POSITION).unwrap();
+		for sym in Symmetry::ALL {
+			let image = state.transform(sym);
+
+			assert_eq!(image.transform(sym.inverse()), state);
+			assert_eq!(image.result(), state.result());
+			assert!(image.validate().is_empty());
+			assert_eq!(perft(&image, 2), perft(&state, 2));
+			assert!(
+				state
+					.legal_moves()
+					.all(|mv| image.is_legal(sym.apply_move(mv)))
+			);
+		}
+
+		let state = parse("4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5").unwrap();
+		assert!(
+			Symmetry::ALL
+				.iter()
+				.all(|&sym| state.transform(sym) == state)
+		);
+	}
+
+	#[test]
+	fn canonical() {
+		let state = parse(POSITION).unwrap();
+		let (canonical, sym) = state.canonical();
+
+		assert_eq!(state.transform(sym), canonical);
+		for sym in Symmetry::ALL {
+			assert_eq!(state.transform(sym).canonical().0, canonical);
+		}
+
+		// X in the corner of a corner board, all 4 such positions share one representative
+		let corner = "9/9_/9_/9_/9_/9_/9_/9_/9_/8_X";
+		for position in [
+			"9/X8_/9_/9_/9_/9_/9_/9_/9_/9_",
+			"9/9_/9_/2_X6_/9_/9_/9_/9_/9_/9_",
+			"9/9_/9_/9_/9_/9_/9_/6_X2_/9_/9_",
+			corner,
+		] {
+			assert_eq!(parse(position).unwrap().canonical().0.to_string(), corner);
+		}
+	}
+}
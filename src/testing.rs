@@ -0,0 +1,49 @@
+//! Helpers shared by the tests of several modules.
+
+use crate::state::{Move, State};
+
+/// Small LCG so the tests are deterministic without pulling in a rng
+pub(crate) struct Lcg(u64);
+
+impl Lcg {
+	pub(crate) fn new(seed: u64) -> Self {
+		Self(seed)
+	}
+
+	/// A number in 0..n
+	pub(crate) fn next(&mut self, n: u64) -> u64 {
+		self.0 = self
+			.0
+			.wrapping_mul(6364136223846793005)
+			.wrapping_add(1442695040888963407);
+		(self.0 >> 33) % n
+	}
+
+	/// One of the legal moves of `state`, `None` once the game is decided
+	pub(crate) fn legal_move(&mut self, state: &State) -> Option<Move> {
+		let moves: Vec<_> = state.legal_moves().collect();
+		(!moves.is_empty()).then(|| moves[self.next(moves.len() as u64) as usize])
+	}
+
+	/// The moves of a game played at random from `start` until it's decided
+	pub(crate) fn game(&mut self, start: &State) -> Vec<Move> {
+		let mut state = start.clone();
+		let mut moves = vec![];
+		while let Some(mv) = self.legal_move(&state) {
+			state.play(mv).unwrap();
+			moves.push(mv);
+		}
+		moves
+	}
+
+	/// Every position of a game played at random from `start`, ending with the decided one
+	pub(crate) fn positions(&mut self, start: &State) -> Vec<State> {
+		let mut state = start.clone();
+		let mut positions = vec![state.clone()];
+		for mv in self.game(start) {
+			state.play(mv).unwrap();
+			positions.push(state.clone());
+		}
+		positions
+	}
+}
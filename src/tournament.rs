@@ -0,0 +1,462 @@
+//! Matches between engines running as child processes, driven by the `utt-match` binary.
+//!
+//! Every pairing plays [`Config::games`] games with colors alternating. Openings are used in
+//! order, each for two consecutive games so both engines get to play both sides of it. A game ends
+//! by the rules, by adjudication when both engines agree on a forced win, or by forfeit when an
+//! engine crashes, hangs or plays an illegal move. A forfeiting engine is restarted for its next
+//! game.
+
+use std::{
+	fmt,
+	io::{self, Write},
+	process::Command,
+	time::Duration,
+};
+
+use crate::{
+	game::{Game, GameMove},
+	process::{EngineError, EngineProcess},
+	protocol::{GoParams, Score},
+	state::{GameResult, Move, Square, State},
+	stats::{Pentanomial, Sprt, Verdict, Wdl},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// Every engine plays every other engine
+	RoundRobin,
+	/// The first engine plays every other engine
+	Gauntlet,
+}
+
+impl Format {
+	/// The pairs of players that meet, as indices
+	pub fn pairings(self, players: usize) -> Vec<(usize, usize)> {
+		match self {
+			Format::RoundRobin => (0..players)
+				.flat_map(|a| (a + 1..players).map(move |b| (a, b)))
+				.collect(),
+			Format::Gauntlet => (1..players).map(|b| (0, b)).collect(),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+	pub format: Format,
+	/// Games per pairing
+	pub games: u32,
+	/// Time per move, sent as `go movetime` and enforced with `stop`
+	pub movetime: Duration,
+	/// Starting positions, the empty board when there are none
+	pub openings: Vec<State>,
+	/// End games early when one engine reports a forced win and the other a forced loss
+	pub adjudicate: bool,
+	/// Stop a match between two engines once this test is decided, on game pairs
+	pub sprt: Option<Sprt>,
+	/// Time allowed for starting an engine and for answering `isready` and `stop`
+	pub grace: Duration,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			format: Format::RoundRobin,
+			games: 2,
+			movetime: Duration::from_millis(100),
+			openings: vec![],
+			adjudicate: true,
+			sprt: None,
+			grace: Duration::from_secs(1),
+		}
+	}
+}
+
+/// An engine taking part in a match
+pub struct Player {
+	pub program: String,
+	pub args: Vec<String>,
+	/// The engine's `id name`, or its program until it has been started
+	pub name: String,
+	process: Option<EngineProcess>,
+}
+
+impl Player {
+	pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+		let program = program.into();
+		Self {
+			name: program.clone(),
+			program,
+			args,
+			process: None,
+		}
+	}
+
+	/// The running engine, starting it if needed, ready for a new game
+	fn start(&mut self, grace: Duration) -> Result<&mut EngineProcess, EngineError> {
+		if self.process.is_none() {
+			let mut command = Command::new(&self.program);
+			command.args(&self.args);
+
+			let mut process = EngineProcess::spawn(command, grace)?;
+			process.grace = grace;
+			self.name.clone_from(&process.name);
+			self.process = Some(process);
+		}
+
+		let process = self.process.as_mut().unwrap();
+		process.new_game()?;
+		Ok(process)
+	}
+}
+
+#[derive(Debug)]
+pub enum Termination {
+	/// Three boards in a row, or no moves left
+	Rules,
+	/// Both engines agreed on a forced win
+	Adjudicated,
+	/// `side` lost because its engine failed
+	Forfeit { side: Square, err: EngineError },
+}
+
+impl fmt::Display for Termination {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Termination::Rules => write!(f, "rules"),
+			Termination::Adjudicated => write!(f, "adjudication"),
+			Termination::Forfeit { side, err } => {
+				let side = if *side == Square::X { "x" } else { "o" };
+				write!(f, "forfeit, {side}: {err}")
+			}
+		}
+	}
+}
+
+/// A finished game, `x` and `o` index into [`Tournament::players`]
+#[derive(Debug)]
+pub struct GameRecord {
+	pub x: usize,
+	pub o: usize,
+	pub opening: State,
+	pub moves: Vec<Move>,
+	pub result: GameResult,
+	pub termination: Termination,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Standing {
+	pub player: usize,
+	pub wins: u32,
+	pub draws: u32,
+	pub losses: u32,
+}
+
+impl Standing {
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	/// Wins plus half the draws
+	pub fn points(&self) -> f64 {
+		self.wins as f64 + self.draws as f64 / 2.0
+	}
+}
+
+pub struct Tournament {
+	pub players: Vec<Player>,
+	pub config: Config,
+	pub games: Vec<GameRecord>,
+}
+
+impl Tournament {
+	pub fn new(players: Vec<Player>, config: Config) -> Self {
+		Self {
+			players,
+			config,
+			games: vec![],
+		}
+	}
+
+	/// Plays every game, calling `on_game` after each one, or stops early once the
+	/// [`sprt`](Self::sprt) is decided
+	pub fn run(&mut self, mut on_game: impl FnMut(&Self, &GameRecord)) {
+		let empty = [State::default()];
+		let openings = if self.config.openings.is_empty() {
+			&empty[..]
+		} else {
+			&self.config.openings[..]
+		}
+		.to_vec();
+
+		'pairings: for (a, b) in self.config.format.pairings(self.players.len()) {
+			for game in 0..self.config.games as usize {
+				let (x, o) = if game % 2 == 0 { (a, b) } else { (b, a) };
+				let opening = &openings[game / 2 % openings.len()];
+
+				let record = self.play(x, o, opening);
+				on_game(self, &record);
+				self.games.push(record);
+
+				if self.sprt() != Verdict::Continue {
+					break 'pairings;
+				}
+			}
+		}
+
+		for player in &mut self.players {
+			if let Some(process) = player.process.take() {
+				let _ = process.quit();
+			}
+		}
+	}
+
+	fn play(&mut self, x: usize, o: usize, opening: &State) -> GameRecord {
+		let mut record = GameRecord {
+			x,
+			o,
+			opening: opening.clone(),
+			moves: vec![],
+			result: GameResult::Ongoing,
+			termination: Termination::Rules,
+		};
+
+		let grace = self.config.grace;
+		for (player, side) in [(x, Square::X), (o, Square::O)] {
+			if let Err(err) = self.players[player].start(grace) {
+				self.forfeit(&mut record, side, err);
+				return record;
+			}
+		}
+
+		let params = GoParams {
+			movetime: Some(self.config.movetime.as_millis() as u64),
+			..GoParams::default()
+		};
+		let mut state = opening.clone();
+		// The score of the previous move, from its player's point of view
+		let mut last_score = None;
+
+		while state.result() == GameResult::Ongoing {
+			let side = state.side_to_move();
+			let player = if side == Square::X { x } else { o };
+			let process = self.players[player].process.as_mut().unwrap();
+
+			let reply = match process.go(&state, &params, self.config.movetime) {
+				Ok(reply) => reply,
+				Err(err) => {
+					self.forfeit(&mut record, side, err);
+					return record;
+				}
+			};
+
+			state.play(reply.best_move).unwrap();
+			record.moves.push(reply.best_move);
+
+			let score = reply.info.iter().rev().find_map(|info| info.score);
+			if self.config.adjudicate {
+				let winner = match (score, last_score) {
+					(Some(Score::Win(_)), Some(Score::Loss(_))) => Some(side),
+					(Some(Score::Loss(_)), Some(Score::Win(_))) => Some(opponent(side)),
+					_ => None,
+				};
+
+				if let Some(winner) = winner {
+					record.result = win_for(winner);
+					record.termination = Termination::Adjudicated;
+					return record;
+				}
+			}
+			last_score = score;
+		}
+
+		record.result = state.result();
+		record
+	}
+
+	fn forfeit(&mut self, record: &mut GameRecord, side: Square, err: EngineError) {
+		let player = if side == Square::X {
+			record.x
+		} else {
+			record.o
+		};
+		// Dropping the process kills it, it's restarted for the next game
+		self.players[player].process = None;
+
+		record.result = win_for(opponent(side));
+		record.termination = Termination::Forfeit { side, err };
+	}
+
+	/// Results of `player` in its games against `opponent`
+	pub fn wdl(&self, player: usize, opponent: usize) -> Wdl {
+		let mut wdl = Wdl::default();
+		for score in self.scores(player, opponent) {
+			match score {
+				1.0 => wdl.wins += 1,
+				0.5 => wdl.draws += 1,
+				_ => wdl.losses += 1,
+			}
+		}
+		wdl
+	}
+
+	/// Results of `player` in its pairs of games against `opponent`, ignoring an unfinished pair
+	pub fn pentanomial(&self, player: usize, opponent: usize) -> Pentanomial {
+		let scores: Vec<_> = self.scores(player, opponent).collect();
+
+		let mut penta = Pentanomial::default();
+		for pair in scores.chunks_exact(2) {
+			penta.add(pair[0], pair[1]);
+		}
+		penta
+	}
+
+	/// Scores of `player` against `opponent`, game by game
+	fn scores(&self, player: usize, opponent: usize) -> impl Iterator<Item = f64> + '_ {
+		self.games
+			.iter()
+			.filter(move |game| {
+				(game.x, game.o) == (player, opponent) || (game.x, game.o) == (opponent, player)
+			})
+			.map(move |game| match (game.result, game.x == player) {
+				(GameResult::XWins, true) | (GameResult::OWins, false) => 1.0,
+				(GameResult::Draw, _) => 0.5,
+				_ => 0.0,
+			})
+	}
+
+	/// The configured SPRT of the first engine against the second, on the game pairs played so far.
+	/// Always [`Verdict::Continue`] without a test or with more than two engines.
+	pub fn sprt(&self) -> Verdict {
+		match self.config.sprt {
+			Some(sprt) if self.players.len() == 2 => sprt.verdict(&self.pentanomial(0, 1)),
+			_ => Verdict::Continue,
+		}
+	}
+
+	/// Scores of every player, best first
+	pub fn standings(&self) -> Vec<Standing> {
+		let mut standings: Vec<_> = (0..self.players.len())
+			.map(|player| Standing {
+				player,
+				..Standing::default()
+			})
+			.collect();
+
+		for game in &self.games {
+			let (x, o) = (game.x, game.o);
+			match game.result {
+				GameResult::XWins => {
+					standings[x].wins += 1;
+					standings[o].losses += 1;
+				}
+				GameResult::OWins => {
+					standings[o].wins += 1;
+					standings[x].losses += 1;
+				}
+				GameResult::Draw => {
+					standings[x].draws += 1;
+					standings[o].draws += 1;
+				}
+				GameResult::Ongoing => {}
+			}
+		}
+
+		standings.sort_by(|a, b| b.points().total_cmp(&a.points()));
+		standings
+	}
+
+	/// Writes the standings as a table
+	pub fn write_table(&self, mut out: impl Write) -> io::Result<()> {
+		let width = self
+			.players
+			.iter()
+			.map(|player| player.name.len())
+			.max()
+			.unwrap_or(0)
+			.max("Engine".len());
+
+		writeln!(
+			out,
+			"{:>4}  {:width$}  {:>5}  {:>4}  {:>5}  {:>6}  {:>6}",
+			"Rank", "Engine", "Games", "Wins", "Draws", "Losses", "Score",
+		)?;
+		for (rank, standing) in self.standings().iter().enumerate() {
+			let score = if standing.games() == 0 {
+				0.0
+			} else {
+				100.0 * standing.points() / standing.games() as f64
+			};
+
+			writeln!(
+				out,
+				"{:>4}  {:width$}  {:>5}  {:>4}  {:>5}  {:>6}  {:>6}",
+				rank + 1,
+				self.players[standing.player].name,
+				standing.games(),
+				standing.wins,
+				standing.draws,
+				standing.losses,
+				format!("{score:.1}%"),
+			)?;
+		}
+
+		Ok(())
+	}
+
+	/// The game record of `game`, `number` counts from 1
+	pub fn record(&self, number: usize, game: &GameRecord) -> Game {
+		// Engines pick their names, and headers can't hold control characters
+		let name = |player: usize| self.players[player].name.replace(char::is_control, " ");
+		let mut record = Game::new(game.opening.clone());
+		for (key, value) in [
+			("Game", number.to_string()),
+			("X", name(game.x)),
+			("O", name(game.o)),
+			("Termination", game.termination.to_string()),
+		] {
+			record.set_header(key, value).unwrap();
+		}
+		record.moves = game.moves.iter().copied().map(GameMove::from).collect();
+		record.result = game.result;
+		record
+	}
+
+	/// Writes the record of one game to the log, followed by a blank line
+	pub fn write_game(
+		&self,
+		mut out: impl Write,
+		number: usize,
+		game: &GameRecord,
+	) -> io::Result<()> {
+		writeln!(out, "{}", self.record(number, game))
+	}
+}
+
+fn opponent(side: Square) -> Square {
+	if side == Square::X {
+		Square::O
+	} else {
+		Square::X
+	}
+}
+
+fn win_for(side: Square) -> GameResult {
+	if side == Square::X {
+		GameResult::XWins
+	} else {
+		GameResult::OWins
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pairings() {
+		assert_eq!(Format::RoundRobin.pairings(3), [(0, 1), (0, 2), (1, 2)]);
+		assert_eq!(Format::Gauntlet.pairings(3), [(0, 1), (0, 2)]);
+		assert_eq!(Format::RoundRobin.pairings(1), []);
+	}
+}
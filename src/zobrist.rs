@@ -0,0 +1,144 @@
+//! Zobrist hashing of positions
+//! A position's key is the XOR of a random key for every mark on the board, one for the active board
+//! and one more when O is to move. The last move isn't part of the key. Keys are generated at
+//! compile time from a fixed seed, so they are the same across runs, platforms and builds and can be
+//! stored alongside positions.
+
+use crate::{
+	bitboard::BitState,
+	state::{Move, Square, State},
+};
+
+const fn splitmix64(state: u64) -> (u64, u64) {
+	let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+	let mut z = state;
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	(state, z ^ (z >> 31))
+}
+
+struct Keys {
+	/// X's keys followed by O's, indexed by [`Move::index`]
+	squares: [u64; 162],
+	/// Indexed by the active board, 0..=9
+	active: [u64; 10],
+	o_to_move: u64,
+}
+
+const KEYS: Keys = {
+	let mut seed = 0x7574_7470_726f_746f;
+	let mut keys = Keys {
+		squares: [0; 162],
+		active: [0; 10],
+		o_to_move: 0,
+	};
+
+	let mut i = 0;
+	while i < 162 {
+		(seed, keys.squares[i]) = splitmix64(seed);
+		i += 1;
+	}
+
+	let mut i = 0;
+	while i < 10 {
+		(seed, keys.active[i]) = splitmix64(seed);
+		i += 1;
+	}
+
+	(_, keys.o_to_move) = splitmix64(seed);
+	keys
+};
+
+/// Key of `mark` on the square `mv`, zero for an empty square
+pub fn square_key(mv: Move, mark: Square) -> u64 {
+	match mark {
+		Square::Empty => 0,
+		Square::X => KEYS.squares[mv.index()],
+		Square::O => KEYS.squares[81 + mv.index()],
+	}
+}
+
+/// Key of the active board, anything above 9 hashes like 9
+pub fn active_key(active: u8) -> u64 {
+	KEYS.active[active.min(9) as usize]
+}
+
+/// Key of the side to move
+pub fn side_key(side: Square) -> u64 {
+	match side {
+		Square::O => KEYS.o_to_move,
+		_ => 0,
+	}
+}
+
+pub fn hash(state: &State) -> u64 {
+	let squares = state.squares.iter().enumerate().fold(0, |key, (i, &sq)| {
+		key ^ square_key(Move::from_index(i).unwrap(), sq)
+	});
+
+	squares ^ active_key(state.active) ^ side_key(state.side_to_move())
+}
+
+pub fn hash_bits(bits: &BitState) -> u64 {
+	let squares = (0..81).fold(0, |key, i| {
+		let mv = Move::from_index(i).unwrap();
+		key ^ square_key(mv, bits.square(mv))
+	});
+
+	squares ^ active_key(bits.active()) ^ side_key(bits.side_to_move())
+}
+
+/// Updates `key` for `mark` being played on `mv`, which moved the active board from `old_active` to
+/// `new_active`. Calling this again with the same arguments takes the move back out of the key.
+pub fn update(key: u64, mv: Move, mark: Square, old_active: u8, new_active: u8) -> u64 {
+	key ^ square_key(mv, mark) ^ active_key(old_active) ^ active_key(new_active) ^ KEYS.o_to_move
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{state::parse, testing::Lcg};
+
+	#[test]
+	fn incremental_matches_full() {
+		let mut rng = Lcg::new(0xda94_2042_e4dd_58b5);
+		let mut seen = std::collections::HashMap::new();
+
+		for _ in 0..100 {
+			let mut state = parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap();
+			let mut key = hash(&state);
+
+			loop {
+				assert_eq!(key, hash(&state));
+				assert_eq!(key, hash_bits(&BitState::from(&state)));
+
+				// Positions only differing in the last move share a key
+				let mut position = state.clone();
+				position.last_move = None;
+				assert_eq!(*seen.entry(key).or_insert(position.clone()), position);
+
+				let Some(mv) = rng.legal_move(&state) else {
+					break;
+				};
+
+				let (mark, old_active) = (state.side_to_move(), state.active);
+				state.play(mv).unwrap();
+				key = update(key, mv, mark, old_active, state.active);
+			}
+		}
+	}
+
+	#[test]
+	fn keys_are_stable() {
+		// Stored keys depend on this never changing
+		assert_eq!(
+			hash(&parse("9/9_/9_/9_/9_/9_/9_/9_/9_/9_").unwrap()),
+			active_key(9)
+		);
+		assert_eq!(active_key(9), 0xd4d5_9880_f208_1412);
+		assert_eq!(
+			hash(&parse("4/9_/9_/9_/9_/4_X4_/9_/9_/9_/9_/e5").unwrap()),
+			0x7daf_6b84_23c0_2704
+		);
+	}
+}
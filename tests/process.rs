@@ -0,0 +1,103 @@
+use std::{process::Command, time::Duration};
+
+use uttprotocol::{
+	process::{EngineError, EngineProcess},
+	protocol::GoParams,
+	state::State,
+};
+
+/// Runs the `mock_engine` binary, misbehaving as `mode` says
+fn mock_engine(mode: &str) -> Command {
+	let mut command = Command::new(env!("CARGO_BIN_EXE_mock_engine"));
+	command.arg(mode);
+	command
+}
+
+fn spawn(mode: &str) -> EngineProcess {
+	let mut engine = EngineProcess::spawn(mock_engine(mode), Duration::from_secs(5)).unwrap();
+	engine.grace = Duration::from_millis(200);
+	engine
+}
+
+const SHORT: Duration = Duration::from_millis(50);
+
+#[test]
+fn plays_moves() {
+	let mut engine = spawn("first");
+	assert_eq!(engine.name, "Mock");
+	assert_eq!(engine.author, "uttprotocol");
+	engine.new_game().unwrap();
+
+	let mut state = State::default();
+	for expected in ["a1", "a2", "b1"] {
+		let reply = engine
+			.go(&state, &GoParams::default(), Duration::from_secs(5))
+			.unwrap();
+		assert_eq!(reply.best_move.to_string(), expected);
+		assert_eq!(reply.info.len(), 1);
+		assert!(!reply.stopped);
+		state.play(reply.best_move).unwrap();
+	}
+
+	assert!(engine.quit().unwrap().unwrap().success());
+}
+
+#[test]
+fn skips_what_it_doesnt_understand() {
+	let mut engine = spawn("chatty");
+	let reply = engine
+		.go(
+			&State::default(),
+			&GoParams::default(),
+			Duration::from_secs(5),
+		)
+		.unwrap();
+
+	assert_eq!(reply.best_move.to_string(), "a1");
+	assert_eq!(
+		reply.info.iter().map(|info| info.depth).collect::<Vec<_>>(),
+		[Some(2), Some(1)]
+	);
+}
+
+#[test]
+fn stops_long_searches() {
+	let mut engine = spawn("stop");
+	let reply = engine
+		.go(&State::default(), &GoParams::default(), SHORT)
+		.unwrap();
+
+	assert!(reply.stopped);
+	assert!(reply.elapsed >= SHORT);
+}
+
+#[test]
+fn detects_failures() {
+	let state = State::default();
+	let params = GoParams::default();
+
+	let err = spawn("crash").go(&state, &params, SHORT).unwrap_err();
+	assert!(
+		matches!(err, EngineError::Crashed { status: Some(status) } if status.code() == Some(3))
+	);
+
+	let err = spawn("hang").go(&state, &params, SHORT).unwrap_err();
+	assert!(matches!(
+		err,
+		EngineError::Timeout {
+			expected: "bestmove"
+		}
+	));
+
+	let mut engine = spawn("illegal");
+	engine.go(&state, &params, SHORT).unwrap();
+	let mut state = state.clone();
+	state.play("a1".parse().unwrap()).unwrap();
+	let err = engine.go(&state, &params, SHORT).unwrap_err();
+	assert!(matches!(err, EngineError::IllegalMove { mv: Some(_) }));
+
+	let err = EngineProcess::spawn(mock_engine("mute"), SHORT)
+		.err()
+		.unwrap();
+	assert!(matches!(err, EngineError::Timeout { expected: "utpok" }));
+}
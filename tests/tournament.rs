@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use uttprotocol::{
+	process::EngineError,
+	state::{GameResult, Square, State},
+	stats::{Sprt, Verdict, Wdl},
+	tournament::{Config, Player, Termination, Tournament},
+};
+
+fn player(mode: &str) -> Player {
+	Player::new(env!("CARGO_BIN_EXE_mock_engine"), vec![mode.to_string()])
+}
+
+#[test]
+fn stops_on_sprt() {
+	let config = Config {
+		games: 1000,
+		sprt: Some(Sprt::default()),
+		..Config::default()
+	};
+	let mut tournament = Tournament::new(vec![player("first"), player("illegal")], config);
+	tournament.run(|_, _| {});
+
+	assert_eq!(tournament.sprt(), Verdict::H1);
+	assert!(tournament.games.len() < 100);
+	assert_eq!(tournament.games.len() % 2, 0);
+}
+
+#[test]
+fn plays_a_match() {
+	let mut e5 = State::default();
+	e5.play("e5".parse().unwrap()).unwrap();
+
+	let config = Config {
+		games: 4,
+		movetime: Duration::from_millis(500),
+		grace: Duration::from_millis(500),
+		openings: vec![State::default(), e5],
+		..Config::default()
+	};
+	let mut tournament = Tournament::new(vec![player("first"), player("illegal")], config);
+
+	let mut log = vec![];
+	tournament.run(|tournament, game| {
+		let number = tournament.games.len() + 1;
+		tournament.write_game(&mut log, number, game).unwrap();
+	});
+
+	// The illegal engine always plays a1, which is only legal while board a is empty and
+	// playable
+	let games = &tournament.games;
+	assert_eq!(
+		games.iter().map(|game| game.x).collect::<Vec<_>>(),
+		[0, 1, 0, 1]
+	);
+	assert_eq!(
+		games
+			.iter()
+			.map(|game| game.moves.len())
+			.collect::<Vec<_>>(),
+		[1, 2, 0, 3]
+	);
+	for game in games {
+		let (loser, result) = if game.x == 1 {
+			(Square::X, GameResult::OWins)
+		} else {
+			(Square::O, GameResult::XWins)
+		};
+		assert_eq!(game.result, result);
+		assert!(matches!(
+			game.termination,
+			Termination::Forfeit { side, err: EngineError::IllegalMove { .. } } if side == loser
+		));
+	}
+
+	let log = String::from_utf8(log).unwrap();
+	assert!(log.starts_with(
+		"[Game \"1\"]\n[X \"Mock\"]\n[O \"Mock\"]\n\
+		 [Termination \"forfeit, o: illegal move a1\"]\n[Result \"1-0\"]\n\n1. a1 1-0\n\n"
+	));
+	assert!(log.contains("\n\n1... e1 2. a1 a2 0-1\n\n"));
+
+	assert_eq!(
+		tournament.wdl(0, 1),
+		Wdl {
+			wins: 4,
+			draws: 0,
+			losses: 0
+		}
+	);
+	assert_eq!(tournament.pentanomial(1, 0).counts, [2, 0, 0, 0, 0]);
+
+	let mut table = vec![];
+	tournament.write_table(&mut table).unwrap();
+	assert_eq!(
+		String::from_utf8(table).unwrap(),
+		"Rank  Engine  Games  Wins  Draws  Losses   Score\n   \
+		 1  Mock        4     4      0       0  100.0%\n   \
+		 2  Mock        4     0      0       4    0.0%\n"
+	);
+}